@@ -1,50 +1,110 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
+use std::collections::{BTreeMap, BTreeSet};
+
 use cosmwasm_std::{
-    to_json_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo, 
-    Response, StdError, StdResult, Uint128,
+    from_json, to_json_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Empty, Env, Event, MessageInfo, 
+    Int128, Order, Response, StdError, StdResult, Storage, Timestamp, Uint128, WasmMsg,
 };
-use cw2::set_contract_version;
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg, TokenInfoResponse};
 
 use crate::error::ContractError;
 use crate::msg::{
-    ExecuteMsg, InstantiateMsg, QueryMsg, GroupResponse, GroupsResponse,
-    ExpenseResponse, ExpensesResponse, DebtsResponse, Balance, BalanceSummaryResponse
+    ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, ReceiveMsg, GroupResponse, GroupsResponse,
+    ExpenseResponse, ExpensesResponse, DebtsResponse, Balance, BalanceSummaryResponse,
+    ExpenseHistoryResponse, SimplifiedDebtsResponse, ConfigResponse, GroupRolesResponse, MemberRole,
+    InvitesResponse, JoinRequestsResponse, JoinCodesResponse, PendingOwnershipResponse,
+    CategoryTotal, CategoryTotalsResponse, ExpenseDate, ExpenseFilter, Payer, DisputeResolution,
 };
 use crate::state::{
-    Group, Expense, Debt, GROUP_COUNT, EXPENSE_COUNT, GROUPS, EXPENSES, 
-    USER_GROUPS, GROUP_EXPENSES, DEBTS
+    Config, Group, GroupPrivacy, GroupRole, GroupStatus, Invite, JoinCode, JoinRequest, PendingOwnership, Expense, Debt, Contribution, ExpenseStatus,
+    ApprovalPolicy, Dispute, DisputeOutcome, ExpenseChange, ExpenseRevision, RemainderPolicy, Share, SplitMode,
+    GROUP_COUNT, EXPENSE_COUNT, GROUPS, EXPENSES, USER_GROUPS, GROUP_EXPENSES, DEBTS, EXPENSE_HISTORY,
+    EXPENSES_BY_RECORDED, EXPENSES_BY_OCCURRED, OPEN_EXPENSES, UNPAID_CHARGES, PAID_CHARGES, CHARGED_TO, Charge,
+    SIMPLIFY_OPT_INS, CONFIG, GROUP_ROLES, INVITES, USER_INVITES, JOIN_REQUESTS,
+    JOIN_CODES, GROUP_JOIN_CODES, PENDING_OWNERS,
 };
+use sha2::{Digest, Sha256};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:expense-splitter";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-// Maximum length of group name
-const MAX_GROUP_NAME_LENGTH: usize = 64;
+// Default maximum length of group name
+const MAX_GROUP_NAME_LENGTH: u32 = 64;
+
+// Default maximum length of expense description
+const MAX_EXPENSE_DESCRIPTION_LENGTH: u32 = 128;
+
+// Default maximum number of members in a group
+const MAX_GROUP_MEMBERS: u32 = 50;
+
+// Maximum length of a group description
+const MAX_GROUP_DESCRIPTION_LENGTH: usize = 512;
+
+// Maximum length of a group's currency label
+const MAX_CURRENCY_LABEL_LENGTH: usize = 16;
+
+// Maximum length of a group's avatar URI
+const MAX_AVATAR_URI_LENGTH: usize = 256;
+
+// Maximum number of tags on a group, and the maximum length of each key and value
+const MAX_GROUP_TAGS: usize = 16;
+const MAX_TAG_LENGTH: usize = 64;
+
+// Maximum number of expense categories a group can define
+const MAX_GROUP_CATEGORIES: usize = 32;
 
-// Maximum length of expense description
-const MAX_EXPENSE_DESCRIPTION_LENGTH: usize = 128;
+// Maximum number of tags on an expense
+const MAX_EXPENSE_TAGS: usize = 10;
 
-// Maximum number of members in a group
-const MAX_GROUP_MEMBERS: usize = 50;
+// How far an expense date may lie before or after the block time
+const MAX_EXPENSE_AGE_SECONDS: u64 = 365 * 24 * 60 * 60;
+const MAX_EXPENSE_LEAD_SECONDS: u64 = 24 * 60 * 60;
+
+// Maximum length of the reason given for disputing an expense
+const MAX_DISPUTE_REASON_LENGTH: usize = 256;
+
+// Denomination debts are settled in unless the config or a group picks another one
+const DEFAULT_DENOM: &str = "uxion";
+
+// Percentage splits are expressed in basis points and must add up to 100%
+const TOTAL_BASIS_POINTS: u32 = 10_000;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
-    _msg: InstantiateMsg,
+    info: MessageInfo,
+    msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     
+    // The instantiator owns the contract unless someone else is named
+    let owner = match msg.owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender,
+    };
+    let config = Config {
+        owner,
+        default_denom: msg.default_denom.unwrap_or_else(|| DEFAULT_DENOM.to_string()),
+        max_group_members: msg.max_group_members.unwrap_or(MAX_GROUP_MEMBERS),
+        max_group_name_length: msg.max_group_name_length.unwrap_or(MAX_GROUP_NAME_LENGTH),
+        max_expense_description_length: msg.max_expense_description_length
+            .unwrap_or(MAX_EXPENSE_DESCRIPTION_LENGTH),
+    };
+    execute::validate_config(&config)?;
+    CONFIG.save(deps.storage, &config)?;
+    
     // Initialize group and expense counters to 0
     GROUP_COUNT.save(deps.storage, &0u64)?;
     EXPENSE_COUNT.save(deps.storage, &0u64)?;
 
     Ok(Response::new()
         .add_attribute("method", "instantiate")
-        .add_attribute("contract_name", CONTRACT_NAME))
+        .add_attribute("contract_name", CONTRACT_NAME)
+        .add_attribute("owner", config.owner))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -55,14 +115,87 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::CreateGroup { name, members } => 
-            execute::create_group(deps, env, info, name, members),
-        ExecuteMsg::AddExpense { group_id, description, amount, split_between } => 
-            execute::add_expense(deps, env, info, group_id, description, amount, split_between),
+        ExecuteMsg::UpdateConfig { 
+            owner, default_denom, max_group_members, max_group_name_length, max_expense_description_length,
+        } => execute::update_config(deps, env, info, execute::ConfigUpdate {
+            owner, default_denom, max_group_members, max_group_name_length, max_expense_description_length,
+        }),
+        ExecuteMsg::CreateGroup { name, members, remainder_policy, denom } => 
+            execute::create_group(deps, env, info, name, members, remainder_policy, denom),
+        ExecuteMsg::AddExpense { 
+            group_id, description, amount, split_between, split_mode, category, tags, occurred_at, payers, paid_by,
+        } => execute::add_expense(deps, env, info, group_id, paid_by, execute::ExpenseDetails {
+            description, amount, split_between, split_mode, category, tags: tags.unwrap_or_default(), occurred_at, payers,
+        }),
+        ExecuteMsg::EditExpense { 
+            id, description, amount, split_between, split_mode, category, tags, occurred_at, payers,
+        } => execute::edit_expense(deps, env, info, id, execute::ExpenseDetails {
+            description, amount, split_between, split_mode, category, tags: tags.unwrap_or_default(), occurred_at, payers,
+        }),
+        ExecuteMsg::ConfirmExpense { id } => 
+            execute::confirm_expense(deps, env, info, id),
+        ExecuteMsg::ApproveExpense { id } => 
+            execute::approve_expense(deps, env, info, id),
+        ExecuteMsg::DisputeExpense { id, reason } => 
+            execute::dispute_expense(deps, env, info, id, reason),
+        ExecuteMsg::ResolveDispute { id, resolution } => 
+            execute::resolve_dispute(deps, env, info, id, resolution),
+        ExecuteMsg::DeleteExpense { id } => 
+            execute::delete_expense(deps, env, info, id),
         ExecuteMsg::SettleDebt { group_id, to, amount } => 
             execute::settle_debt(deps, env, info, group_id, to, amount),
         ExecuteMsg::SettleAllDebts { group_id } => 
             execute::settle_all_debts(deps, env, info, group_id),
+        ExecuteMsg::Receive(wrapper) => 
+            execute::receive_cw20(deps, env, info, wrapper),
+        ExecuteMsg::UpdateCw20Token { group_id, token } => 
+            execute::update_cw20_token(deps, env, info, group_id, token),
+        ExecuteMsg::UpdateSettlementDenom { group_id, denom } => 
+            execute::update_settlement_denom(deps, env, info, group_id, denom),
+        ExecuteMsg::SetSimplifyDebtsOptIn { group_id, opt_in } => 
+            execute::set_simplify_debts_opt_in(deps, env, info, group_id, opt_in),
+        ExecuteMsg::SimplifyDebts { group_id } => 
+            execute::simplify_debts(deps, env, info, group_id),
+        ExecuteMsg::GrantRole { group_id, member, role } => 
+            execute::grant_role(deps, env, info, group_id, member, role),
+        ExecuteMsg::RevokeRole { group_id, member } => 
+            execute::grant_role(deps, env, info, group_id, member, GroupRole::Member),
+        ExecuteMsg::UpdateApprovalPolicy { group_id, policy } => 
+            execute::update_approval_policy(deps, env, info, group_id, policy),
+        ExecuteMsg::UpdateRemainderPolicy { group_id, remainder_policy } => 
+            execute::update_remainder_policy(deps, env, info, group_id, remainder_policy),
+        ExecuteMsg::UpdateGroupPrivacy { group_id, privacy } => 
+            execute::update_group_privacy(deps, env, info, group_id, privacy),
+        ExecuteMsg::InviteMember { group_id, invitee } => 
+            execute::invite_member(deps, env, info, group_id, invitee),
+        ExecuteMsg::RevokeInvite { group_id, invitee } => 
+            execute::revoke_invite(deps, env, info, group_id, invitee),
+        ExecuteMsg::ApproveJoinRequest { group_id, requester } => 
+            execute::approve_join_request(deps, env, info, group_id, requester),
+        ExecuteMsg::RejectJoinRequest { group_id, requester } => 
+            execute::reject_join_request(deps, env, info, group_id, requester),
+        ExecuteMsg::CreateJoinCode { group_id, public_key, expires_at, max_uses } => 
+            execute::create_join_code(deps, env, info, group_id, public_key, expires_at, max_uses),
+        ExecuteMsg::RevokeJoinCode { public_key } => 
+            execute::revoke_join_code(deps, env, info, public_key),
+        ExecuteMsg::JoinGroupWithCode { public_key, signature } => 
+            execute::join_group_with_code(deps, env, info, public_key, signature),
+        ExecuteMsg::UpdateGroup { group_id, name, description, currency_label, avatar_uri, tags, categories } => 
+            execute::update_group(deps, env, info, group_id, execute::GroupMetadata {
+                name, description, currency_label, avatar_uri, tags, categories,
+            }),
+        ExecuteMsg::ProposeOwnershipTransfer { group_id, new_owner, expires_at } => 
+            execute::propose_ownership_transfer(deps, env, info, group_id, new_owner, expires_at),
+        ExecuteMsg::AcceptOwnership { group_id } => 
+            execute::accept_ownership(deps, env, info, group_id),
+        ExecuteMsg::CancelOwnershipTransfer { group_id } => 
+            execute::cancel_ownership_transfer(deps, env, info, group_id),
+        ExecuteMsg::ArchiveGroup { group_id } => 
+            execute::archive_group(deps, env, info, group_id),
+        ExecuteMsg::ReopenGroup { group_id } => 
+            execute::reopen_group(deps, env, info, group_id),
+        ExecuteMsg::RemoveMember { group_id, member } => 
+            execute::remove_member(deps, env, info, group_id, member),
         ExecuteMsg::JoinGroup { group_id } => 
             execute::join_group(deps, env, info, group_id),
         ExecuteMsg::LeaveGroup { group_id } => 
@@ -73,32 +206,84 @@ pub fn execute(
 pub mod execute {
     use super::*;
 
-    pub fn create_group(
+    // Fields of UpdateConfig; anything left as None keeps its current value
+    pub struct ConfigUpdate {
+        pub owner: Option<String>,
+        pub default_denom: Option<String>,
+        pub max_group_members: Option<u32>,
+        pub max_group_name_length: Option<u32>,
+        pub max_expense_description_length: Option<u32>,
+    }
+
+    pub fn update_config(
         deps: DepsMut,
-        env: Env,
+        _env: Env,
         info: MessageInfo,
-        name: String,
-        members: Vec<String>,
+        update: ConfigUpdate,
     ) -> Result<Response, ContractError> {
-        // Validate group name
-        if name.trim().is_empty() {
-            return Err(ContractError::InvalidGroupName { 
-                reason: "Group name cannot be empty".to_string() 
-            });
+        let mut config = CONFIG.load(deps.storage)?;
+        
+        // Only the contract owner can change the config
+        if info.sender != config.owner {
+            return Err(ContractError::Unauthorized {});
         }
         
-        if name.len() > MAX_GROUP_NAME_LENGTH {
-            return Err(ContractError::InvalidGroupName { 
-                reason: format!("Group name exceeds maximum length of {}", MAX_GROUP_NAME_LENGTH) 
-            });
+        if let Some(owner) = update.owner {
+            config.owner = deps.api.addr_validate(&owner)?;
+        }
+        if let Some(default_denom) = update.default_denom {
+            config.default_denom = default_denom;
+        }
+        if let Some(max_group_members) = update.max_group_members {
+            config.max_group_members = max_group_members;
+        }
+        if let Some(max_group_name_length) = update.max_group_name_length {
+            config.max_group_name_length = max_group_name_length;
+        }
+        if let Some(max_expense_description_length) = update.max_expense_description_length {
+            config.max_expense_description_length = max_expense_description_length;
         }
         
-        // Validate members
-        if members.len() > MAX_GROUP_MEMBERS {
+        validate_config(&config)?;
+        CONFIG.save(deps.storage, &config)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "update_config")
+            .add_attribute("owner", config.owner)
+            .add_attribute("default_denom", config.default_denom))
+    }
+
+    pub fn validate_config(config: &Config) -> Result<(), ContractError> {
+        validate_denom(&config.default_denom)?;
+        
+        if config.max_group_members == 0 
+            || config.max_group_name_length == 0 
+            || config.max_expense_description_length == 0 
+        {
             return Err(ContractError::CustomError { 
-                val: format!("Too many members. Maximum is {}", MAX_GROUP_MEMBERS)
+                val: "Limits must be greater than zero".to_string() 
             });
         }
+        Ok(())
+    }
+
+    pub fn create_group(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        name: String,
+        members: Vec<String>,
+        remainder_policy: Option<RemainderPolicy>,
+        denom: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        
+        // Validate group name
+        validate_group_name(&config, &name)?;
+        
+        // Validate the settlement denomination
+        let denom = denom.unwrap_or(config.default_denom);
+        validate_denom(&denom)?;
         
         // Parse and validate member addresses
         let mut validated_members = Vec::with_capacity(members.len() + 1);
@@ -115,6 +300,13 @@ pub mod execute {
             }
         }
         
+        // Validate members, counting the creator
+        if validated_members.len() > config.max_group_members as usize {
+            return Err(ContractError::CustomError { 
+                val: format!("Too many members. Maximum is {}", config.max_group_members)
+            });
+        }
+        
         // Get and increment group count
         let id = GROUP_COUNT.update(deps.storage, |count| -> StdResult<_> {
             Ok(count + 1)
@@ -127,6 +319,18 @@ pub mod execute {
             creator: info.sender.clone(),
             members: validated_members.clone(),
             created_at: env.block.time,
+            remainder_policy: remainder_policy.unwrap_or(RemainderPolicy::RoundRobin),
+            denom,
+            cw20_token: None,
+            privacy: GroupPrivacy::Open,
+            departed_members: vec![],
+            status: GroupStatus::Active,
+            description: None,
+            currency_label: None,
+            avatar_uri: None,
+            tags: BTreeMap::new(),
+            categories: vec![],
+            approval_policy: ApprovalPolicy::None,
         };
         
         GROUPS.save(deps.storage, id, &group)?;
@@ -158,63 +362,38 @@ pub mod execute {
         env: Env,
         info: MessageInfo,
         group_id: u64,
-        description: String,
-        amount: Uint128,
-        split_between: Vec<String>,
+        paid_by: Option<String>,
+        mut details: ExpenseDetails,
     ) -> Result<Response, ContractError> {
-        // Validate expense description
-        if description.trim().is_empty() {
-            return Err(ContractError::InvalidExpenseDescription { 
-                reason: "Expense description cannot be empty".to_string() 
-            });
-        }
-        
-        if description.len() > MAX_EXPENSE_DESCRIPTION_LENGTH {
-            return Err(ContractError::InvalidExpenseDescription { 
-                reason: format!("Description exceeds maximum length of {}", MAX_EXPENSE_DESCRIPTION_LENGTH) 
-            });
-        }
+        validate_expense_details(deps.storage, &details)?;
         
-        // Validate amount
-        if amount.is_zero() {
-            return Err(ContractError::InvalidAmount { 
-                reason: "Amount must be greater than zero".to_string() 
-            });
-        }
+        // Undated expenses happened when they were recorded
+        let occurred_at = details.occurred_at.unwrap_or(env.block.time);
+        validate_expense_date(occurred_at, &env)?;
+        details.occurred_at = Some(occurred_at);
         
         // Check if the group exists
         let group = GROUPS.may_load(deps.storage, group_id)?
             .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
         
-        // Check if the sender is in the group
-        if !group.members.contains(&info.sender) {
-            return Err(ContractError::UserNotInGroup {});
-        }
+        // Viewers can't charge anyone
+        ensure_can_post(deps.storage, &group, &info.sender)?;
         
-        // Determine who shares this expense
-        let split_members: Vec<Addr> = if split_between.is_empty() {
-            // If no specific members are provided, all group members share the expense
-            group.members.clone()
-        } else {
-            // Otherwise, validate the provided addresses
-            let mut validated_split = Vec::with_capacity(split_between.len());
-            
-            for member in split_between {
-                let addr = deps.api.addr_validate(&member)?;
-                
-                // Check if the address is a member of the group
-                if !group.members.contains(&addr) {
-                    return Err(ContractError::UserNotInGroup {});
-                }
-                
-                // Don't add duplicates
-                if !validated_split.contains(&addr) {
-                    validated_split.push(addr);
-                }
+        // Admins can record what someone else paid; anyone else's entry waits for the payers to confirm it
+        let paid_by = match paid_by {
+            Some(paid_by) => deps.api.addr_validate(&paid_by)?,
+            None => info.sender.clone(),
+        };
+        let recorded_by = if paid_by != info.sender {
+            if !group.members.contains(&paid_by) {
+                return Err(ContractError::UserNotInGroup {});
             }
-            
-            validated_split
+            Some(info.sender.clone())
+        } else {
+            None
         };
+        let plain_member = member_role(deps.storage, &group, &info.sender)? == Some(GroupRole::Member);
         
         // Get and increment expense count
         let id = EXPENSE_COUNT.update(deps.storage, |count| -> StdResult<_> {
@@ -222,18 +401,30 @@ pub mod execute {
         })?;
         
         // Create and save the expense
-        let expense = Expense {
-            id,
-            group_id,
-            description,
-            amount,
-            paid_by: info.sender.clone(),
-            split_between: split_members.clone(),  // Clone here to avoid moving split_members
-            timestamp: env.block.time,
-            settled: false,
+        let mut expense = build_expense(deps.as_ref(), &group, id, paid_by, env.block.time, details)?;
+        expense.recorded_by = recorded_by;
+        
+        // The sender vouches for their own payment; everyone else it credits has to confirm theirs
+        expense.confirmations = expense.payers
+            .iter()
+            .filter(|contribution| contribution.payer == info.sender)
+            .map(|contribution| contribution.payer.clone())
+            .collect();
+        let needs_confirmation = plain_member && !unconfirmed_payers(&expense).is_empty();
+        
+        // Adding an expense approves it on the sender's part
+        if approvers(&expense).contains(&info.sender) {
+            expense.approvals.push(info.sender.clone());
+        }
+        expense.status = if needs_confirmation {
+            ExpenseStatus::PendingConfirmation
+        } else {
+            approval_status(&group, &expense)
         };
         
         EXPENSES.save(deps.storage, id, &expense)?;
+        index_expense(deps.storage, &expense)?;
+        track_open_expense(deps.storage, &expense)?;
         
         // Update the group's expense list
         let group_expenses = GROUP_EXPENSES
@@ -245,821 +436,6405 @@ pub mod execute {
         
         GROUP_EXPENSES.save(deps.storage, group_id, &updated_group_expenses)?;
         
-        // Calculate and update debts
-        let split_members_count = split_members.len() as u128;
-        let split_amount = if split_members_count == 0 {
-            Uint128::zero()
-        } else {
-            amount.checked_div(Uint128::from(split_members_count)).unwrap_or(Uint128::zero())
-        };
-        
         // Update debts - the person who paid is owed money by others
-        for member in &split_members {  // Use reference here to avoid moving split_members
-            // Skip the person who paid (they don't owe themselves)
-            if member == &info.sender {
-                continue;
-            }
-            
-            // Update the debt from this member to the payer
-            let debt_key = (group_id, member, &info.sender);
-            let current_debt = DEBTS.may_load(deps.storage, debt_key)?.unwrap_or(Uint128::zero());
-            DEBTS.save(deps.storage, debt_key, &(current_debt + split_amount))?;
-        }
+        apply_expense_debts(deps.storage, &expense)?;
 
-        Ok(Response::new()
+        let mut response = Response::new()
             .add_attribute("action", "add_expense")
             .add_attribute("id", id.to_string())
             .add_attribute("group_id", group_id.to_string())
-            .add_attribute("paid_by", info.sender)
-            .add_attribute("amount", amount)
-            .add_attribute("split_between", split_members.len().to_string()))
+            .add_attribute("paid_by", expense.paid_by.clone())
+            .add_attribute("amount", expense.amount)
+            .add_attribute("split_between", expense.split_between.len().to_string());
+        let pending = expense.status != ExpenseStatus::Active;
+        if let Some(recorded_by) = &expense.recorded_by {
+            response = response.add_attribute("recorded_by", recorded_by.clone());
+        }
+        if pending || expense.recorded_by.is_some() {
+            response = response.add_attribute("pending", pending.to_string());
+        }
+        Ok(response)
     }
 
-    pub fn settle_debt(
+    pub fn confirm_expense(
         deps: DepsMut,
         _env: Env,
         info: MessageInfo,
-        group_id: u64,
-        to: String,
-        amount: Uint128,
+        id: u64,
     ) -> Result<Response, ContractError> {
-        // Validate the recipient address
-        let recipient = deps.api.addr_validate(&to)?;
-        
-        // Cannot settle with yourself
-        if info.sender == recipient {
-            return Err(ContractError::CannotSettleWithSelf {});
-        }
-        
-        // Check if the group exists
-        let group = GROUPS.may_load(deps.storage, group_id)?
+        let mut expense = EXPENSES.may_load(deps.storage, id)?
+            .ok_or(ContractError::ExpenseNotFound {})?;
+        let group = GROUPS.may_load(deps.storage, expense.group_id)?
             .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
         
-        // Check if both sender and recipient are in the group
-        if !group.members.contains(&info.sender) || !group.members.contains(&recipient) {
-            return Err(ContractError::UserNotInGroup {});
-        }
-        
-        // Validate amount
-        if amount.is_zero() {
-            return Err(ContractError::InvalidAmount { 
-                reason: "Amount must be greater than zero".to_string() 
-            });
+        // Only the payers can vouch for what they paid
+        if !expense.payers.iter().any(|contribution| contribution.payer == info.sender) {
+            return Err(ContractError::Unauthorized {});
         }
-        
-        // Check if there is a debt from sender to recipient
-        let debt_key = (group_id, &info.sender, &recipient);
-        let debt = DEBTS.may_load(deps.storage, debt_key)?.unwrap_or(Uint128::zero());
-        
-        if debt.is_zero() {
-            return Err(ContractError::NoDebtExists {});
+        if !group.members.contains(&info.sender) {
+            return Err(ContractError::UserNotInGroup {});
         }
-        
-        // Check if trying to pay more than owed
-        if amount > debt {
-            return Err(ContractError::InvalidPayment {});
+        if expense.status != ExpenseStatus::PendingConfirmation {
+            return Err(ContractError::ExpenseNotPending {});
         }
-        
-        // Check if the sender has sent enough XION tokens with the transaction
-        // Get the amount of XION sent
-        let xion_amount = match info.funds.iter().find(|coin| coin.denom == "uxion") {
-            Some(coin) => coin.amount,
-            None => Uint128::zero(),
-        };
-        
-        if xion_amount < amount {
-            return Err(ContractError::InsufficientFunds { 
-                needed: amount.to_string(), 
-                available: xion_amount.to_string()
-            });
+        if expense.confirmations.contains(&info.sender) {
+            return Err(ContractError::AlreadyConfirmed {});
         }
         
-        // Update the debt
-        let new_debt = debt - amount;
-        if new_debt.is_zero() {
-            DEBTS.remove(deps.storage, debt_key);
-        } else {
-            DEBTS.save(deps.storage, debt_key, &new_debt)?;
+        // Once every payer has confirmed, it may still need the approval of the members it charges
+        expense.confirmations.push(info.sender.clone());
+        if unconfirmed_payers(&expense).is_empty() {
+            expense.status = approval_status(&group, &expense);
         }
+        EXPENSES.save(deps.storage, id, &expense)?;
+        track_open_expense(deps.storage, &expense)?;
+        apply_expense_debts(deps.storage, &expense)?;
         
-        // Create message to transfer tokens to recipient
-        let transfer_msg = BankMsg::Send {
-            to_address: recipient.to_string(),
-            amount: vec![Coin {
-                denom: "uxion".to_string(),
-                amount,
-            }],
-        };
-        
-        // Return success response with transfer message
         Ok(Response::new()
-            .add_message(transfer_msg)
-            .add_attribute("action", "settle_debt")
-            .add_attribute("group_id", group_id.to_string())
-            .add_attribute("from", info.sender)
-            .add_attribute("to", recipient)
-            .add_attribute("amount", amount.to_string())
-            .add_attribute("remaining_debt", new_debt.to_string()))
+            .add_attribute("action", "confirm_expense")
+            .add_attribute("id", id.to_string())
+            .add_attribute("group_id", expense.group_id.to_string())
+            .add_attribute("paid_by", info.sender)
+            .add_attribute("status", format!("{:?}", expense.status)))
     }
 
-    pub fn settle_all_debts(
+    pub fn approve_expense(
         deps: DepsMut,
         _env: Env,
         info: MessageInfo,
-        group_id: u64,
+        id: u64,
     ) -> Result<Response, ContractError> {
-        // Check if the group exists
-        let group = GROUPS.may_load(deps.storage, group_id)?
+        let mut expense = EXPENSES.may_load(deps.storage, id)?
+            .ok_or(ContractError::ExpenseNotFound {})?;
+        let group = GROUPS.may_load(deps.storage, expense.group_id)?
             .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
         
-        // Check if sender is in the group
         if !group.members.contains(&info.sender) {
             return Err(ContractError::UserNotInGroup {});
         }
+        if !approvers(&expense).contains(&info.sender) {
+            return Err(ContractError::Unauthorized {});
+        }
+        if expense.status != ExpenseStatus::PendingApproval {
+            return Err(ContractError::ExpenseNotPending {});
+        }
+        if expense.approvals.contains(&info.sender) {
+            return Err(ContractError::AlreadyApproved {});
+        }
         
-        // Calculate total debt owed by the sender to others in this group
-        let mut total_debt = Uint128::zero();
-        let mut payments: Vec<(Addr, Uint128)> = Vec::new();
+        expense.approvals.push(info.sender.clone());
+        expense.status = approval_status(&group, &expense);
+        EXPENSES.save(deps.storage, id, &expense)?;
+        track_open_expense(deps.storage, &expense)?;
         
-        // Find all debts the sender owes
-        for creditor in &group.members {
-            if creditor == &info.sender {
-                continue; // Skip self
-            }
-            
-            let debt_key = (group_id, &info.sender, creditor);
-            if let Some(debt) = DEBTS.may_load(deps.storage, debt_key)? {
-                if !debt.is_zero() {
-                    total_debt += debt;
-                    payments.push((creditor.clone(), debt));
-                    
-                    // Remove the debt
-                    DEBTS.remove(deps.storage, debt_key);
-                }
-            }
-        }
+        // The debts appear once enough members have approved
+        apply_expense_debts(deps.storage, &expense)?;
         
-        // Check if there are any debts to settle
-        if total_debt.is_zero() {
-            return Err(ContractError::NoDebtExists {});
+        Ok(Response::new()
+            .add_attribute("action", "approve_expense")
+            .add_attribute("id", id.to_string())
+            .add_attribute("group_id", expense.group_id.to_string())
+            .add_attribute("approved_by", info.sender)
+            .add_attribute("status", format!("{:?}", expense.status)))
+    }
+
+    pub fn dispute_expense(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        id: u64,
+        reason: String,
+    ) -> Result<Response, ContractError> {
+        let reason = reason.trim().to_string();
+        if reason.is_empty() || reason.len() > MAX_DISPUTE_REASON_LENGTH {
+            return Err(ContractError::InvalidDispute { 
+                reason: format!("Reason must be between 1 and {} characters", MAX_DISPUTE_REASON_LENGTH) 
+            });
         }
         
-        // Check if the sender has sent enough XION tokens with the transaction
-        let xion_amount = match info.funds.iter().find(|coin| coin.denom == "uxion") {
-            Some(coin) => coin.amount,
-            None => Uint128::zero(),
-        };
+        let previous = EXPENSES.may_load(deps.storage, id)?
+            .ok_or(ContractError::ExpenseNotFound {})?;
+        let group = GROUPS.may_load(deps.storage, previous.group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
         
-        if xion_amount < total_debt {
-            return Err(ContractError::InsufficientFunds { 
-                needed: total_debt.to_string(), 
-                available: xion_amount.to_string()
+        // Only members the expense charges can object to it
+        if !group.members.contains(&info.sender) {
+            return Err(ContractError::UserNotInGroup {});
+        }
+        if !approvers(&previous).contains(&info.sender) {
+            return Err(ContractError::Unauthorized {});
+        }
+        if is_disputing(&previous, &info.sender) {
+            return Err(ContractError::InvalidDispute { 
+                reason: "You already have an open dispute on this expense".to_string() 
+            });
+        }
+        // Freezing a share that was already paid towards would hand the payment straight back
+        if has_paid_towards(deps.storage, &previous, &info.sender)? {
+            return Err(ContractError::InvalidDispute { 
+                reason: "Your share has already been paid, in part or in full".to_string() 
             });
         }
         
-        // Create messages to send tokens to each creditor
-        let mut messages: Vec<CosmosMsg> = Vec::with_capacity(payments.len());
-        let mut attrs = vec![
-            ("action".to_string(), "settle_all_debts".to_string()),
-            ("group_id".to_string(), group_id.to_string()),
-            ("from".to_string(), info.sender.to_string()),
-            ("total_paid".to_string(), total_debt.to_string()),
-        ];
-        
-        for (index, (creditor, amount)) in payments.iter().enumerate() {
-            let transfer_msg = BankMsg::Send {
-                to_address: creditor.to_string(),
-                amount: vec![Coin {
-                    denom: "uxion".to_string(),
-                    amount: *amount,
-                }],
-            };
-            
-            messages.push(transfer_msg.into());
-            
-            // Add payment details as attributes (limited to first few to avoid overflow)
-            if index < 5 {
-                attrs.push((
-                    format!("paid_to_{}", index).to_string(),
-                    creditor.to_string()
-                ));
-                attrs.push((
-                    format!("amount_{}", index).to_string(),
-                    amount.to_string()
-                ));
+        let mut expense = previous.clone();
+        expense.disputes.push(Dispute {
+            member: info.sender.clone(),
+            reason,
+            raised_at: env.block.time,
+            outcome: None,
+            resolved_by: None,
+            resolved_at: None,
+        });
+        match previous.status {
+            // Before approval, a dispute holds the whole expense back
+            ExpenseStatus::PendingApproval | ExpenseStatus::Disputed => {
+                expense.approvals.retain(|member| *member != info.sender);
+                expense.status = ExpenseStatus::Disputed;
             }
+            // Afterwards, only the sender's share is frozen
+            ExpenseStatus::Active => {}
+            ExpenseStatus::PendingConfirmation => return Err(ContractError::ExpenseNotPending {}),
         }
+        EXPENSES.save(deps.storage, id, &expense)?;
+        track_open_expense(deps.storage, &expense)?;
+        reverse_expense_debts(deps.storage, &previous)?;
+        apply_expense_debts(deps.storage, &expense)?;
         
-        // Add total payments count
-        attrs.push(("total_payments".to_string(), payments.len().to_string()));
-        
-        // Return success response with transfer messages
         Ok(Response::new()
-            .add_messages(messages)
-            .add_attributes(attrs))
+            .add_attribute("action", "dispute_expense")
+            .add_attribute("id", id.to_string())
+            .add_attribute("group_id", expense.group_id.to_string())
+            .add_attribute("disputed_by", info.sender))
     }
 
-    pub fn join_group(
+    pub fn resolve_dispute(
         deps: DepsMut,
-        _env: Env,
+        env: Env,
         info: MessageInfo,
-        group_id: u64,
+        id: u64,
+        resolution: DisputeResolution,
     ) -> Result<Response, ContractError> {
-        // Check if the group exists
-        let mut group = GROUPS.may_load(deps.storage, group_id)?
+        let previous = EXPENSES.may_load(deps.storage, id)?
+            .ok_or(ContractError::ExpenseNotFound {})?;
+        let group = GROUPS.may_load(deps.storage, previous.group_id)?
             .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
         
-        // Check if the user is already a member
-        if group.members.contains(&info.sender) {
-            return Err(ContractError::UserAlreadyInGroup {});
+        if !previous.disputes.iter().any(|dispute| dispute.outcome.is_none()) {
+            return Err(ContractError::InvalidDispute { 
+                reason: "Expense has no open disputes".to_string() 
+            });
         }
         
-        // Add the user to the group
-        group.members.push(info.sender.clone());
-        GROUPS.save(deps.storage, group_id, &group)?;
+        // Admins settle every open dispute at once; a member can only take back their own
+        let is_admin = matches!(
+            member_role(deps.storage, &group, &info.sender)?,
+            Some(GroupRole::Owner | GroupRole::Admin)
+        );
+        let withdrawing = !is_admin 
+            && matches!(resolution, DisputeResolution::Uphold {}) 
+            && is_disputing(&previous, &info.sender);
+        if !is_admin && !withdrawing {
+            return Err(ContractError::Unauthorized {});
+        }
         
-        // Update the user's groups
-        let user_groups = USER_GROUPS
-            .may_load(deps.storage, &info.sender)?
-            .unwrap_or_default();
+        let outcome = match resolution {
+            DisputeResolution::Uphold {} => {
+                let mut expense = previous.clone();
+                let outcome = if withdrawing { DisputeOutcome::Withdrawn } else { DisputeOutcome::Upheld };
+                let only = if withdrawing { Some(&info.sender) } else { None };
+                let closed = close_disputes(&mut expense, outcome, &info.sender, &env, only);
+                
+                // Upholding a charge that was still waiting for approval approves it for the disputers
+                if expense.status == ExpenseStatus::Disputed {
+                    if !withdrawing {
+                        for member in closed {
+                            if !expense.approvals.contains(&member) {
+                                expense.approvals.push(member);
+                            }
+                        }
+                    }
+                    if !expense.disputes.iter().any(|dispute| dispute.outcome.is_none()) {
+                        expense.status = approval_status(&group, &expense);
+                    }
+                }
+                
+                EXPENSES.save(deps.storage, id, &expense)?;
+                track_open_expense(deps.storage, &expense)?;
+                reverse_expense_debts(deps.storage, &previous)?;
+                apply_expense_debts(deps.storage, &expense)?;
+                outcome
+            }
+            DisputeResolution::Amend { amount, split_between, split_mode, payers } => {
+                let details = ExpenseDetails {
+                    description: previous.description.clone(),
+                    amount,
+                    split_between,
+                    split_mode,
+                    category: previous.category.clone(),
+                    tags: previous.tags.clone(),
+                    occurred_at: Some(previous.occurred_at),
+                    payers: kept_payers(&previous, payers),
+                };
+                validate_expense_details(deps.storage, &details)?;
+                
+                // An admin's amendment is final: it counts without another round of approval
+                let mut expense = rebuild_expense(deps.as_ref(), &group, &previous, details)?;
+                close_disputes(&mut expense, DisputeOutcome::Amended, &info.sender, &env, None);
+                expense.status = ExpenseStatus::Active;
+                replace_expense(deps.storage, &env, &info.sender, previous, &expense)?;
+                DisputeOutcome::Amended
+            }
+            DisputeResolution::Cancel {} => {
+                remove_expense(deps.storage, &previous)?;
+                let mut expense = previous;
+                close_disputes(&mut expense, DisputeOutcome::Cancelled, &info.sender, &env, None);
+                record_revision(deps.storage, expense, ExpenseChange::Deleted, &info.sender, &env)?;
+                DisputeOutcome::Cancelled
+            }
+        };
+        
+        Ok(Response::new()
+            .add_attribute("action", "resolve_dispute")
+            .add_attribute("id", id.to_string())
+            .add_attribute("group_id", group.id.to_string())
+            .add_attribute("resolved_by", info.sender)
+            .add_attribute("outcome", format!("{:?}", outcome)))
+    }
+
+    pub fn edit_expense(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        id: u64,
+        mut details: ExpenseDetails,
+    ) -> Result<Response, ContractError> {
+        validate_expense_details(deps.storage, &details)?;
+        
+        let previous = EXPENSES.may_load(deps.storage, id)?
+            .ok_or(ContractError::ExpenseNotFound {})?;
+        
+        // Keep the date unless a new one is given; an unchanged date may have aged past the limit
+        match details.occurred_at {
+            Some(occurred_at) if occurred_at != previous.occurred_at => validate_expense_date(occurred_at, &env)?,
+            Some(_) => {}
+            None => details.occurred_at = Some(previous.occurred_at),
+        }
+        details.payers = kept_payers(&previous, details.payers);
+        
+        let group = GROUPS.may_load(deps.storage, previous.group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        // Only the payer, whoever recorded it or a group admin can change an expense
+        ensure_can_modify_expense(deps.storage, &group, &previous, &info.sender)?;
+        
+        // Rebuild the expense in place; editing it answers any open disputes
+        let mut expense = rebuild_expense(deps.as_ref(), &group, &previous, details)?;
+        close_disputes(&mut expense, DisputeOutcome::Amended, &info.sender, &env, None);
+        
+        // The members it charges approve the new version from scratch; editing it approves it on the sender's part
+        if approvers(&expense).contains(&info.sender) {
+            expense.approvals.push(info.sender.clone());
+        }
         
-        let mut updated_user_groups = user_groups;
-        updated_user_groups.push(group_id);
+        // Payers keep their confirmation while what they're credited with is unchanged, and the sender
+        // vouches for their own payment. Unless an admin makes the change, anyone else has to confirm.
+        let confirmed_before = |payer: &Addr| {
+            previous.status != ExpenseStatus::PendingConfirmation || previous.confirmations.contains(payer)
+        };
+        expense.confirmations = expense.payers
+            .iter()
+            .filter(|contribution| {
+                contribution.payer == info.sender 
+                    || (previous.payers.contains(contribution) && confirmed_before(&contribution.payer))
+            })
+            .map(|contribution| contribution.payer.clone())
+            .collect();
+        let plain_member = member_role(deps.storage, &group, &info.sender)? == Some(GroupRole::Member);
+        let needs_confirmation = !unconfirmed_payers(&expense).is_empty()
+            && (plain_member || previous.status == ExpenseStatus::PendingConfirmation);
+        expense.status = if needs_confirmation {
+            ExpenseStatus::PendingConfirmation
+        } else {
+            approval_status(&group, &expense)
+        };
         
-        USER_GROUPS.save(deps.storage, &info.sender, &updated_user_groups)?;
+        replace_expense(deps.storage, &env, &info.sender, previous, &expense)?;
         
         Ok(Response::new()
-            .add_attribute("action", "join_group")
+            .add_attribute("action", "edit_expense")
+            .add_attribute("id", id.to_string())
+            .add_attribute("group_id", expense.group_id.to_string())
+            .add_attribute("edited_by", info.sender)
+            .add_attribute("amount", expense.amount))
+    }
+
+    pub fn delete_expense(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        id: u64,
+    ) -> Result<Response, ContractError> {
+        let expense = EXPENSES.may_load(deps.storage, id)?
+            .ok_or(ContractError::ExpenseNotFound {})?;
+        let group = GROUPS.may_load(deps.storage, expense.group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        // Only the payer, whoever recorded it or a group admin can remove an expense
+        ensure_can_modify_expense(deps.storage, &group, &expense, &info.sender)?;
+        
+        remove_expense(deps.storage, &expense)?;
+        
+        let group_id = expense.group_id;
+        record_revision(deps.storage, expense, ExpenseChange::Deleted, &info.sender, &env)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "delete_expense")
+            .add_attribute("id", id.to_string())
             .add_attribute("group_id", group_id.to_string())
-            .add_attribute("user", info.sender))
+            .add_attribute("deleted_by", info.sender))
     }
 
-    pub fn leave_group(
+    pub fn settle_debt(
         deps: DepsMut,
         _env: Env,
         info: MessageInfo,
         group_id: u64,
+        to: String,
+        amount: Uint128,
     ) -> Result<Response, ContractError> {
+        // Validate the recipient address
+        let recipient = deps.api.addr_validate(&to)?;
+        
         // Check if the group exists
-        let mut group = GROUPS.may_load(deps.storage, group_id)?
+        let group = GROUPS.may_load(deps.storage, group_id)?
             .ok_or(ContractError::GroupNotFound {})?;
         
-        // Check if the user is a member
-        if !group.members.contains(&info.sender) {
+        let payment = Payment::Native { denom: group.denom.clone(), funds: info.funds };
+        pay_debt(deps, &group, &info.sender, &recipient, amount, payment)
+    }
+
+    pub fn settle_all_debts(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        let payment = Payment::Native { denom: group.denom.clone(), funds: info.funds };
+        pay_all_debts(deps, &group, &info.sender, payment)
+    }
+
+    pub fn receive_cw20(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        wrapper: Cw20ReceiveMsg,
+    ) -> Result<Response, ContractError> {
+        // The tokens were sent by `wrapper.sender`; `info.sender` is the token contract
+        let sender = deps.api.addr_validate(&wrapper.sender)?;
+        let msg: ReceiveMsg = from_json(&wrapper.msg)?;
+        let group_id = match &msg {
+            ReceiveMsg::SettleDebt { group_id, .. } | ReceiveMsg::SettleAllDebts { group_id } => *group_id,
+        };
+        
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        // Only the token the group agreed on can be used to pay
+        if group.cw20_token.as_ref() != Some(&info.sender) {
+            return Err(ContractError::UnsupportedToken { token: info.sender.to_string() });
+        }
+        
+        let payment = Payment::Cw20 { token: info.sender, amount: wrapper.amount };
+        match msg {
+            ReceiveMsg::SettleDebt { to, .. } => {
+                let recipient = deps.api.addr_validate(&to)?;
+                pay_debt(deps, &group, &sender, &recipient, wrapper.amount, payment)
+            }
+            ReceiveMsg::SettleAllDebts { .. } => 
+                pay_all_debts(deps, &group, &sender, payment),
+        }
+    }
+
+    // The funds a settlement is paid with
+    enum Payment {
+        // Coins sent along with the message; only `denom` counts towards the payment
+        Native { denom: String, funds: Vec<Coin> },
+        // Tokens the contract received from a CW20 contract
+        Cw20 { token: Addr, amount: Uint128 },
+    }
+
+    impl Payment {
+        // How much of the settlement asset was received
+        fn received(&self) -> Uint128 {
+            match self {
+                Payment::Native { denom, funds } => funds
+                    .iter()
+                    .filter(|coin| &coin.denom == denom)
+                    .map(|coin| coin.amount)
+                    .sum(),
+                Payment::Cw20 { amount, .. } => *amount,
+            }
+        }
+
+        // Denom or token address, for error messages
+        fn asset(&self) -> String {
+            match self {
+                Payment::Native { denom, .. } => denom.clone(),
+                Payment::Cw20 { token, .. } => token.to_string(),
+            }
+        }
+
+        fn transfer(&self, to: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
+            Ok(match self {
+                Payment::Native { denom, .. } => BankMsg::Send {
+                    to_address: to.to_string(),
+                    amount: vec![Coin {
+                        denom: denom.clone(),
+                        amount,
+                    }],
+                }.into(),
+                Payment::Cw20 { token, .. } => WasmMsg::Execute {
+                    contract_addr: token.to_string(),
+                    msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: to.to_string(),
+                        amount,
+                    })?,
+                    funds: vec![],
+                }.into(),
+            })
+        }
+
+        // Sends back everything beyond the `spent` amount, including coins in other denoms
+        fn refund(&self, to: &Addr, spent: Uint128) -> StdResult<Option<CosmosMsg>> {
+            let surplus = self.received() - spent;
+            match self {
+                Payment::Native { denom, funds } => {
+                    let mut coins: Vec<Coin> = funds
+                        .iter()
+                        .filter(|coin| &coin.denom != denom && !coin.amount.is_zero())
+                        .cloned()
+                        .collect();
+                    if !surplus.is_zero() {
+                        coins.push(Coin { denom: denom.clone(), amount: surplus });
+                    }
+                    if coins.is_empty() {
+                        return Ok(None);
+                    }
+                    coins.sort_by(|a, b| a.denom.cmp(&b.denom));
+                    Ok(Some(BankMsg::Send { to_address: to.to_string(), amount: coins }.into()))
+                }
+                Payment::Cw20 { .. } if surplus.is_zero() => Ok(None),
+                Payment::Cw20 { .. } => self.transfer(to, surplus).map(Some),
+            }
+        }
+    }
+
+    // Pays `amount` of what `payer` owes `recipient` and refunds the rest of the payment
+    fn pay_debt(
+        deps: DepsMut,
+        group: &Group,
+        payer: &Addr,
+        recipient: &Addr,
+        amount: Uint128,
+        payment: Payment,
+    ) -> Result<Response, ContractError> {
+        // Cannot settle with yourself
+        if payer == recipient {
+            return Err(ContractError::CannotSettleWithSelf {});
+        }
+        
+        // Check if both sender and recipient are in the group (departed members can still settle)
+        if !is_participant(group, payer) || !is_participant(group, recipient) {
             return Err(ContractError::UserNotInGroup {});
         }
         
-        // Check if user has any unsettled debts in this group
-        let mut has_debts = false;
+        // Validate amount
+        if amount.is_zero() {
+            return Err(ContractError::InvalidAmount { 
+                reason: "Amount must be greater than zero".to_string() 
+            });
+        }
         
-        // Check debts user owes to others
-        for member in &group.members {
-            if member == &info.sender {
-                continue;
+        // Check if there is a debt from sender to recipient
+        let debt_key = (group.id, payer, recipient);
+        let debt = DEBTS.may_load(deps.storage, debt_key)?.unwrap_or(Uint128::zero());
+        
+        if debt.is_zero() {
+            return Err(ContractError::NoDebtExists {});
+        }
+        
+        // Check if trying to pay more than owed
+        if amount > debt {
+            return Err(ContractError::InvalidPayment {});
+        }
+        
+        // Check if the sender has sent enough with the transaction
+        let received = payment.received();
+        if received < amount {
+            return Err(ContractError::InsufficientFunds { 
+                needed: format!("{}{}", amount, payment.asset()), 
+                available: format!("{}{}", received, payment.asset())
+            });
+        }
+        
+        // Update the debt
+        let new_debt = debt - amount;
+        if new_debt.is_zero() {
+            DEBTS.remove(deps.storage, debt_key);
+        } else {
+            DEBTS.save(deps.storage, debt_key, &new_debt)?;
+        }
+        settle_edges(deps.storage, group.id, &[(payer.clone(), recipient.clone())])?;
+        release_departed_members(deps.storage, group.clone())?;
+        
+        // Return success response with transfer message
+        let response = Response::new()
+            .add_message(payment.transfer(recipient, amount)?)
+            .add_attribute("action", "settle_debt")
+            .add_attribute("group_id", group.id.to_string())
+            .add_attribute("from", payer)
+            .add_attribute("to", recipient)
+            .add_attribute("amount", amount.to_string())
+            .add_attribute("remaining_debt", new_debt.to_string());
+        
+        // Return anything the sender attached beyond the payment
+        Ok(match payment.refund(payer, amount)? {
+            Some(refund) => response
+                .add_message(refund)
+                .add_attribute("refunded", (received - amount).to_string()),
+            None => response,
+        })
+    }
+
+    // Pays off everything `payer` owes in the group and refunds the rest of the payment
+    fn pay_all_debts(
+        deps: DepsMut,
+        group: &Group,
+        payer: &Addr,
+        payment: Payment,
+    ) -> Result<Response, ContractError> {
+        // Check if sender is in the group (departed members can still settle)
+        if !is_participant(group, payer) {
+            return Err(ContractError::UserNotInGroup {});
+        }
+        
+        // Calculate total debt owed by the sender to others in this group
+        let mut total_debt = Uint128::zero();
+        let mut payments: Vec<(Addr, Uint128)> = Vec::new();
+        
+        // Find all debts the sender owes
+        for creditor in participants(group) {
+            if creditor == payer {
+                continue; // Skip self
             }
             
-            let debt_key = (group_id, &info.sender, member);
+            let debt_key = (group.id, payer, creditor);
             if let Some(debt) = DEBTS.may_load(deps.storage, debt_key)? {
                 if !debt.is_zero() {
-                    has_debts = true;
-                    break;
+                    total_debt += debt;
+                    payments.push((creditor.clone(), debt));
                 }
             }
         }
         
-        // Check debts others owe to user
-        if !has_debts {
-            for member in &group.members {
-                if member == &info.sender {
-                    continue;
-                }
-                
-                let debt_key = (group_id, member, &info.sender);
-                if let Some(debt) = DEBTS.may_load(deps.storage, debt_key)? {
-                    if !debt.is_zero() {
-                        has_debts = true;
-                        break;
-                    }
-                }
-            }
+        // Check if there are any debts to settle
+        if total_debt.is_zero() {
+            return Err(ContractError::NoDebtExists {});
         }
         
-        // Cannot leave group with unsettled debts
-        if has_debts {
-            return Err(ContractError::CustomError { 
-                val: "Cannot leave group with unsettled debts".to_string() 
+        // Check if the sender has sent enough with the transaction
+        let received = payment.received();
+        if received < total_debt {
+            return Err(ContractError::InsufficientFunds { 
+                needed: format!("{}{}", total_debt, payment.asset()), 
+                available: format!("{}{}", received, payment.asset())
             });
         }
         
-        // Remove user from the group
-        group.members.retain(|member| member != &info.sender);
+        // Create messages to send tokens to each creditor
+        let mut messages: Vec<CosmosMsg> = Vec::with_capacity(payments.len());
+        let mut attrs = vec![
+            ("action".to_string(), "settle_all_debts".to_string()),
+            ("group_id".to_string(), group.id.to_string()),
+            ("from".to_string(), payer.to_string()),
+            ("total_paid".to_string(), total_debt.to_string()),
+        ];
         
-        // If group is now empty, remove it completely
-        if group.members.is_empty() {
-            GROUPS.remove(deps.storage, group_id);
+        for (index, (creditor, amount)) in payments.iter().enumerate() {
+            // Remove the debt
+            DEBTS.remove(deps.storage, (group.id, payer, creditor));
             
-            // Also clean up any group-related data
-            GROUP_EXPENSES.remove(deps.storage, group_id);
-        } else {
-            // Otherwise save the updated group
-            GROUPS.save(deps.storage, group_id, &group)?;
+            messages.push(payment.transfer(creditor, *amount)?);
+            
+            // Add payment details as attributes (limited to first few to avoid overflow)
+            if index < 5 {
+                attrs.push((
+                    format!("paid_to_{}", index).to_string(),
+                    creditor.to_string()
+                ));
+                attrs.push((
+                    format!("amount_{}", index).to_string(),
+                    amount.to_string()
+                ));
+            }
         }
         
-        // Update the user's groups
-        let user_groups = USER_GROUPS.may_load(deps.storage, &info.sender)?.unwrap_or_default();
-        let updated_user_groups: Vec<u64> = user_groups.into_iter()
-            .filter(|&id| id != group_id)
-            .collect();
+        let edges: Vec<(Addr, Addr)> = payments.iter().map(|(creditor, _)| (payer.clone(), creditor.clone())).collect();
+        settle_edges(deps.storage, group.id, &edges)?;
+        release_departed_members(deps.storage, group.clone())?;
         
-        if updated_user_groups.is_empty() {
-            USER_GROUPS.remove(deps.storage, &info.sender);
-        } else {
-            USER_GROUPS.save(deps.storage, &info.sender, &updated_user_groups)?;
+        // Add total payments count
+        attrs.push(("total_payments".to_string(), payments.len().to_string()));
+        
+        // Return anything the sender attached beyond the payment
+        if let Some(refund) = payment.refund(payer, total_debt)? {
+            messages.push(refund);
+            attrs.push(("refunded".to_string(), (received - total_debt).to_string()));
         }
         
+        // Return success response with transfer messages
         Ok(Response::new()
-            .add_attribute("action", "leave_group")
-            .add_attribute("group_id", group_id.to_string())
-            .add_attribute("user", info.sender))
+            .add_messages(messages)
+            .add_attributes(attrs))
     }
-}
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::GetGroup { id } => 
-            to_json_binary(&query::get_group(deps, id)?),
-        QueryMsg::GetUserGroups { user, limit, start_after } => 
-            to_json_binary(&query::get_user_groups(deps, user, limit, start_after)?),
-        QueryMsg::GetExpense { id } => 
-            to_json_binary(&query::get_expense(deps, id)?),
-        QueryMsg::GetGroupExpenses { group_id, limit, start_after } => 
-            to_json_binary(&query::get_group_expenses(deps, group_id, limit, start_after)?),
-        QueryMsg::GetDebts { group_id } => 
-            to_json_binary(&query::get_debts(deps, group_id)?),
-        QueryMsg::GetBalanceSummary { group_id, user } => 
-            to_json_binary(&query::get_balance_summary(deps, group_id, user)?),
+    pub fn update_settlement_denom(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        denom: String,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        // Only group admins can change how the group settles
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        validate_denom(&denom)?;
+        
+        // Existing debts were agreed in the current denom
+        if has_outstanding_debts(deps.storage, group_id) {
+            return Err(ContractError::OutstandingDebts {});
+        }
+        
+        group.denom = denom;
+        GROUPS.save(deps.storage, group_id, &group)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "update_settlement_denom")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("denom", group.denom))
     }
-}
 
-pub mod query {
-    use super::*;
-
-    // Default and maximum number of items to return in a query
-    const DEFAULT_LIMIT: u32 = 10;
-    const MAX_LIMIT: u32 = 30;
-
-    pub fn get_group(deps: Deps, id: u64) -> StdResult<GroupResponse> {
-        let group = GROUPS.load(deps.storage, id)?;
-        Ok(GroupResponse { group })
-    }
-
-    pub fn get_user_groups(
-        deps: Deps,
-        user: String,
-        limit: Option<u32>,
-        start_after: Option<u64>,
-    ) -> StdResult<GroupsResponse> {
-        let user_addr = deps.api.addr_validate(&user)?;
-        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-
-        // Get the groups this user belongs to
-        let user_group_ids = USER_GROUPS
-            .may_load(deps.storage, &user_addr)?
-            .unwrap_or_default();
-
-        // Filter by start_after if provided
-        let filtered_ids: Vec<u64> = if let Some(start_id) = start_after {
-            user_group_ids
-                .into_iter()
-                .filter(|&id| id > start_id)
-                .collect()
-        } else {
-            user_group_ids
+    pub fn update_cw20_token(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        token: Option<String>,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        // Only group admins can change how the group settles
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        // Existing debts were agreed with the current token in mind
+        if has_outstanding_debts(deps.storage, group_id) {
+            return Err(ContractError::OutstandingDebts {});
+        }
+        
+        let token = match token {
+            Some(token) => {
+                let token = deps.api.addr_validate(&token)?;
+                // Make sure the address actually is a CW20 token
+                let _: TokenInfoResponse = deps.querier
+                    .query_wasm_smart(&token, &Cw20QueryMsg::TokenInfo {})
+                    .map_err(|_| ContractError::UnsupportedToken { token: token.to_string() })?;
+                Some(token)
+            }
+            None => None,
         };
-
-        // Sort by ID (ascending)
-        let mut sorted_ids = filtered_ids;
-        sorted_ids.sort();
-
-        // Apply limit
-        let limited_ids = sorted_ids.into_iter().take(limit);
-
-        // Load groups from IDs
-        let groups: Vec<Group> = limited_ids
-            .map(|id| GROUPS.load(deps.storage, id))
-            .collect::<StdResult<Vec<_>>>()?;
-
-        Ok(GroupsResponse { groups })
+        
+        group.cw20_token = token;
+        GROUPS.save(deps.storage, group_id, &group)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "update_cw20_token")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("token", group.cw20_token.map(String::from).unwrap_or_default()))
     }
 
-    pub fn get_expense(deps: Deps, id: u64) -> StdResult<ExpenseResponse> {
-        let expense = EXPENSES.load(deps.storage, id)?;
-        Ok(ExpenseResponse { expense })
+    pub fn set_simplify_debts_opt_in(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        opt_in: bool,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        // Check if sender is in the group (departed members' debts are rewritten too)
+        if !is_participant(&group, &info.sender) {
+            return Err(ContractError::UserNotInGroup {});
+        }
+        
+        let mut opted_in = SIMPLIFY_OPT_INS.may_load(deps.storage, group_id)?.unwrap_or_default();
+        opted_in.retain(|member| member != info.sender);
+        if opt_in {
+            opted_in.push(info.sender.clone());
+        }
+        SIMPLIFY_OPT_INS.save(deps.storage, group_id, &opted_in)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "set_simplify_debts_opt_in")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("user", info.sender)
+            .add_attribute("opt_in", opt_in.to_string()))
     }
 
-    pub fn get_group_expenses(
-        deps: Deps,
+    pub fn simplify_debts(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
         group_id: u64,
-        limit: Option<u32>,
-        start_after: Option<u64>,
-    ) -> StdResult<ExpensesResponse> {
-        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-
+    ) -> Result<Response, ContractError> {
         // Check if the group exists
-        if GROUPS.may_load(deps.storage, group_id)?.is_none() {
-            return Err(StdError::not_found("Group"));
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        // Check if sender is in the group
+        if !is_participant(&group, &info.sender) {
+            return Err(ContractError::UserNotInGroup {});
         }
-
-        // Get the expense IDs for this group
-        let expense_ids = GROUP_EXPENSES
-            .may_load(deps.storage, group_id)?
-            .unwrap_or_default();
-
-        // Filter by start_after if provided
-        let filtered_ids: Vec<u64> = if let Some(start_id) = start_after {
-            expense_ids
-                .into_iter()
-                .filter(|&id| id > start_id)
-                .collect()
-        } else {
-            expense_ids
-        };
-
-        // Sort by ID (ascending) - this is a simplification, may want to sort by timestamp in a real app
-        let mut sorted_ids = filtered_ids;
-        sorted_ids.sort();
-
-        // Apply limit
-        let limited_ids = sorted_ids.into_iter().take(limit);
-
-        // Load expenses from IDs
-        let expenses: Vec<Expense> = limited_ids
-            .map(|id| EXPENSES.load(deps.storage, id))
-            .collect::<StdResult<Vec<_>>>()?;
-
-        Ok(ExpensesResponse { expenses })
+        
+        // Rewriting who owes whom needs everyone's consent
+        let opted_in = SIMPLIFY_OPT_INS.may_load(deps.storage, group_id)?.unwrap_or_default();
+        if !participants(&group).all(|member| opted_in.contains(member)) {
+            return Err(ContractError::SimplificationNotApproved {});
+        }
+        
+        let transfers = query::simplified_debts(deps.storage, group_id)?;
+        
+        // Replace the stored debts with the simplified set
+        let existing: Vec<(Addr, Addr)> = DEBTS
+            .sub_prefix(group_id)
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        let removed = existing.len();
+        for (debtor, creditor) in &existing {
+            DEBTS.remove(deps.storage, (group_id, debtor, creditor));
+        }
+        for transfer in &transfers {
+            DEBTS.save(deps.storage, (group_id, &transfer.debtor, &transfer.creditor), &transfer.amount)?;
+        }
+        // Consent covers this rewrite only; later expenses need a fresh round of opt-ins
+        SIMPLIFY_OPT_INS.remove(deps.storage, group_id);
+        let debtors: BTreeSet<Addr> = existing.into_iter().map(|(debtor, _)| debtor).collect();
+        move_charges(deps.storage, group_id, &debtors, &transfers)?;
+        release_departed_members(deps.storage, group)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "simplify_debts")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("previous_debts", removed.to_string())
+            .add_attribute("simplified_debts", transfers.len().to_string()))
     }
 
-    pub fn get_debts(deps: Deps, group_id: u64) -> StdResult<DebtsResponse> {
+    pub fn grant_role(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        member: String,
+        role: GroupRole,
+    ) -> Result<Response, ContractError> {
+        let member = deps.api.addr_validate(&member)?;
+        
         // Check if the group exists
-        let group = GROUPS.may_load(deps.storage, group_id)?.ok_or_else(|| {
-            StdError::not_found("Group")
-        })?;
-
-        let mut debts = Vec::new();
-
-        // Go through all possible pairs of members to find debts
-        for debtor in &group.members {
-            for creditor in &group.members {
-                if debtor == creditor {
-                    continue;
-                }
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        let sender_role = member_role(deps.storage, &group, &info.sender)?;
+        
+        let current = member_role(deps.storage, &group, &member)?
+            .ok_or(ContractError::UserNotInGroup {})?;
+        
+        // There is exactly one owner, and it is always the creator
+        if current == GroupRole::Owner || role == GroupRole::Owner {
+            return Err(ContractError::InvalidRole { 
+                reason: "the owner role can't be granted or taken away".to_string() 
+            });
+        }
+        
+        // Only the owner decides who is an admin
+        if (current == GroupRole::Admin || role == GroupRole::Admin) && sender_role != Some(GroupRole::Owner) {
+            return Err(ContractError::Unauthorized {});
+        }
+        
+        if role == GroupRole::Member {
+            GROUP_ROLES.remove(deps.storage, (group_id, &member));
+        } else {
+            GROUP_ROLES.save(deps.storage, (group_id, &member), &role)?;
+        }
+        
+        Ok(Response::new()
+            .add_attribute("action", "grant_role")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("member", member)
+            .add_attribute("role", format!("{:?}", role)))
+    }
 
-                let debt_key = (group_id, debtor, creditor);
-                if let Some(amount) = DEBTS.may_load(deps.storage, debt_key)? {
-                    if !amount.is_zero() {
-                        debts.push(Debt {
-                            debtor: debtor.clone(),
-                            creditor: creditor.clone(),
-                            amount,
-                        });
-                    }
-                }
+    pub fn update_approval_policy(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        policy: ApprovalPolicy,
+    ) -> Result<Response, ContractError> {
+        if let ApprovalPolicy::Quorum { basis_points } = policy {
+            if basis_points == 0 || basis_points > TOTAL_BASIS_POINTS {
+                return Err(ContractError::InvalidApprovalPolicy { 
+                    reason: format!("Quorum must be between 1 and {} basis points", TOTAL_BASIS_POINTS) 
+                });
             }
         }
-
-        Ok(DebtsResponse { debts })
+        
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        // Expenses already waiting for approval are held to the new policy from their next approval on
+        group.approval_policy = policy;
+        GROUPS.save(deps.storage, group_id, &group)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "update_approval_policy")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("policy", format!("{:?}", policy)))
     }
 
-    pub fn get_balance_summary(
-        deps: Deps,
+    pub fn update_remainder_policy(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
         group_id: u64,
-        user: String,
-    ) -> StdResult<BalanceSummaryResponse> {
-        let user_addr = deps.api.addr_validate(&user)?;
+        remainder_policy: RemainderPolicy,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        // Existing shares are kept; the policy applies to expenses added or edited from now on
+        group.remainder_policy = remainder_policy;
+        GROUPS.save(deps.storage, group_id, &group)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "update_remainder_policy")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("remainder_policy", format!("{:?}", remainder_policy)))
+    }
 
+    pub fn update_group_privacy(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        privacy: GroupPrivacy,
+    ) -> Result<Response, ContractError> {
         // Check if the group exists
-        let group = GROUPS.may_load(deps.storage, group_id)?.ok_or_else(|| {
-            StdError::not_found("Group")
-        })?;
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        group.privacy = privacy;
+        GROUPS.save(deps.storage, group_id, &group)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "update_group_privacy")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("privacy", format!("{:?}", privacy)))
+    }
 
-        // Check if the user is in the group
-        if !group.members.contains(&user_addr) {
-            return Err(StdError::generic_err("User is not a member of this group"));
+    pub fn invite_member(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        invitee: String,
+    ) -> Result<Response, ContractError> {
+        let invitee = deps.api.addr_validate(&invitee)?;
+        
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        if group.members.contains(&invitee) {
+            return Err(ContractError::UserAlreadyInGroup {});
         }
+        if INVITES.has(deps.storage, (group_id, &invitee)) {
+            return Err(ContractError::AlreadyInvited {});
+        }
+        
+        let invite = Invite {
+            group_id,
+            invitee: invitee.clone(),
+            invited_by: info.sender.clone(),
+            created_at: env.block.time,
+        };
+        INVITES.save(deps.storage, (group_id, &invitee), &invite)?;
+        
+        let mut user_invites = USER_INVITES.may_load(deps.storage, &invitee)?.unwrap_or_default();
+        user_invites.push(group_id);
+        USER_INVITES.save(deps.storage, &invitee, &user_invites)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "invite_member")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("invitee", invitee)
+            .add_attribute("invited_by", info.sender))
+    }
 
-        let mut balances = Vec::new();
-        let mut total_owed = Uint128::zero();
-        let mut total_owed_to = Uint128::zero();
-
-        // Calculate balances with each other member
-        for other in &group.members {
-            if other == &user_addr {
-                continue;
-            }
-
-            // Amount user owes to other
-            let debt_key = (group_id, &user_addr, other);
-            let user_owes = DEBTS.may_load(deps.storage, debt_key)?.unwrap_or(Uint128::zero());
-
-            // Amount other owes to user
-            let credit_key = (group_id, other, &user_addr);
-            let other_owes = DEBTS.may_load(deps.storage, credit_key)?.unwrap_or(Uint128::zero());
+    pub fn revoke_invite(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        invitee: String,
+    ) -> Result<Response, ContractError> {
+        let invitee = deps.api.addr_validate(&invitee)?;
+        
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        // The invitee can always decline
+        if info.sender != invitee {
+            ensure_admin(deps.storage, &group, &info.sender)?;
+        }
+        
+        if !INVITES.has(deps.storage, (group_id, &invitee)) {
+            return Err(ContractError::NotInvited {});
+        }
+        remove_invite(deps.storage, group_id, &invitee)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "revoke_invite")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("invitee", invitee)
+            .add_attribute("revoked_by", info.sender))
+    }
 
-            // Calculate net balance
-            if !user_owes.is_zero() || !other_owes.is_zero() {
-                let (amount, direction) = if user_owes > other_owes {
-                    total_owed += user_owes - other_owes;
-                    (user_owes - other_owes, -1) // Negative direction means user owes
-                } else {
-                    total_owed_to += other_owes - user_owes;
-                    (other_owes - user_owes, 1)  // Positive direction means user is owed
-                };
+    pub fn approve_join_request(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        requester: String,
+    ) -> Result<Response, ContractError> {
+        let requester = deps.api.addr_validate(&requester)?;
+        
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        if !JOIN_REQUESTS.has(deps.storage, (group_id, &requester)) {
+            return Err(ContractError::JoinRequestNotFound {});
+        }
+        JOIN_REQUESTS.remove(deps.storage, (group_id, &requester));
+        
+        add_member(deps.storage, &mut group, &requester)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "approve_join_request")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("user", requester)
+            .add_attribute("approved_by", info.sender))
+    }
 
-                balances.push(Balance {
-                    other_user: other.clone(),
-                    amount,
-                    direction,
+    pub fn reject_join_request(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        requester: String,
+    ) -> Result<Response, ContractError> {
+        let requester = deps.api.addr_validate(&requester)?;
+        
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        if !JOIN_REQUESTS.has(deps.storage, (group_id, &requester)) {
+            return Err(ContractError::JoinRequestNotFound {});
+        }
+        JOIN_REQUESTS.remove(deps.storage, (group_id, &requester));
+        
+        Ok(Response::new()
+            .add_attribute("action", "reject_join_request")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("user", requester)
+            .add_attribute("rejected_by", info.sender))
+    }
+
+    // Fields of UpdateGroup; anything left as None keeps its current value
+    pub struct GroupMetadata {
+        pub name: Option<String>,
+        pub description: Option<String>,
+        pub currency_label: Option<String>,
+        pub avatar_uri: Option<String>,
+        pub tags: Option<BTreeMap<String, String>>,
+        pub categories: Option<Vec<String>>,
+    }
+
+    pub fn update_group(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        update: GroupMetadata,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        let mut event = Event::new("group_updated")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("updated_by", &info.sender);
+        
+        if let Some(name) = update.name {
+            validate_group_name(&CONFIG.load(deps.storage)?, &name)?;
+            event = event.add_attribute("name", &name);
+            group.name = name;
+        }
+        if let Some(description) = update.description {
+            validate_metadata("description", &description, MAX_GROUP_DESCRIPTION_LENGTH)?;
+            event = event.add_attribute("description", &description);
+            group.description = Some(description).filter(|description| !description.is_empty());
+        }
+        if let Some(currency_label) = update.currency_label {
+            validate_metadata("currency_label", &currency_label, MAX_CURRENCY_LABEL_LENGTH)?;
+            event = event.add_attribute("currency_label", &currency_label);
+            group.currency_label = Some(currency_label).filter(|label| !label.is_empty());
+        }
+        if let Some(avatar_uri) = update.avatar_uri {
+            validate_metadata("avatar_uri", &avatar_uri, MAX_AVATAR_URI_LENGTH)?;
+            event = event.add_attribute("avatar_uri", &avatar_uri);
+            group.avatar_uri = Some(avatar_uri).filter(|uri| !uri.is_empty());
+        }
+        if let Some(tags) = update.tags {
+            if tags.len() > MAX_GROUP_TAGS {
+                return Err(ContractError::InvalidGroupMetadata { 
+                    reason: format!("at most {} tags are allowed", MAX_GROUP_TAGS) 
+                });
+            }
+            for (key, value) in &tags {
+                if key.trim().is_empty() {
+                    return Err(ContractError::InvalidGroupMetadata { 
+                        reason: "tag keys cannot be empty".to_string() 
+                    });
+                }
+                validate_metadata("tag key", key, MAX_TAG_LENGTH)?;
+                validate_metadata("tag value", value, MAX_TAG_LENGTH)?;
+                event = event.add_attribute(format!("tag.{}", key), value);
+            }
+            group.tags = tags;
+        }
+        if let Some(categories) = update.categories {
+            if categories.len() > MAX_GROUP_CATEGORIES {
+                return Err(ContractError::InvalidGroupMetadata { 
+                    reason: format!("at most {} categories are allowed", MAX_GROUP_CATEGORIES) 
                 });
             }
+            for (index, category) in categories.iter().enumerate() {
+                if category.trim().is_empty() {
+                    return Err(ContractError::InvalidGroupMetadata { 
+                        reason: "categories cannot be empty".to_string() 
+                    });
+                }
+                validate_metadata("category", category, MAX_TAG_LENGTH)?;
+                if categories[..index].contains(category) {
+                    return Err(ContractError::InvalidGroupMetadata { 
+                        reason: format!("category {} is listed more than once", category) 
+                    });
+                }
+            }
+            
+            // Expenses keep their category, so one that is still in use can't be dropped
+            let removed: Vec<&String> = group.categories.iter()
+                .filter(|category| !categories.contains(category))
+                .collect();
+            if !removed.is_empty() {
+                for id in GROUP_EXPENSES.may_load(deps.storage, group_id)?.unwrap_or_default() {
+                    let expense = EXPENSES.load(deps.storage, id)?;
+                    if let Some(category) = expense.category.filter(|category| removed.contains(&category)) {
+                        return Err(ContractError::InvalidGroupMetadata { 
+                            reason: format!("category {} is still used by expense {}", category, id) 
+                        });
+                    }
+                }
+            }
+            event = event.add_attribute("categories", categories.join(","));
+            group.categories = categories;
+        }
+        
+        GROUPS.save(deps.storage, group_id, &group)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "update_group")
+            .add_attribute("group_id", group_id.to_string())
+            .add_event(event))
+    }
+
+    pub fn propose_ownership_transfer(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        new_owner: String,
+        expires_at: Option<Timestamp>,
+    ) -> Result<Response, ContractError> {
+        let new_owner = deps.api.addr_validate(&new_owner)?;
+        
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        // Only the owner can hand the group over
+        if info.sender != group.creator {
+            return Err(ContractError::Unauthorized {});
+        }
+        if new_owner == group.creator {
+            return Err(ContractError::InvalidRole { 
+                reason: "the new owner must be someone else".to_string() 
+            });
+        }
+        if !group.members.contains(&new_owner) {
+            return Err(ContractError::UserNotInGroup {});
+        }
+        if expires_at.is_some_and(|expires_at| expires_at <= env.block.time) {
+            return Err(ContractError::OwnershipTransferExpired {});
+        }
+        
+        let pending = PendingOwnership {
+            group_id,
+            new_owner: new_owner.clone(),
+            proposed_by: info.sender.clone(),
+            proposed_at: env.block.time,
+            expires_at,
+        };
+        PENDING_OWNERS.save(deps.storage, group_id, &pending)?;
+        
+        let mut event = Event::new("group_ownership_proposed")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("owner", info.sender)
+            .add_attribute("new_owner", &new_owner);
+        if let Some(expires_at) = expires_at {
+            event = event.add_attribute("expires_at", expires_at.to_string());
+        }
+        
+        Ok(Response::new()
+            .add_attribute("action", "propose_ownership_transfer")
+            .add_attribute("group_id", group_id.to_string())
+            .add_event(event))
+    }
+
+    pub fn accept_ownership(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        group_id: u64,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        let pending = PENDING_OWNERS.may_load(deps.storage, group_id)?
+            .filter(|pending| pending.new_owner == info.sender)
+            .ok_or(ContractError::NoPendingOwnership {})?;
+        if pending.expires_at.is_some_and(|expires_at| expires_at <= env.block.time) {
+            return Err(ContractError::OwnershipTransferExpired {});
+        }
+        
+        // The offer only stands while the new owner is still a member
+        if !group.members.contains(&info.sender) {
+            return Err(ContractError::UserNotInGroup {});
+        }
+        
+        // The previous owner stays on as an admin
+        let previous_owner = group.creator.clone();
+        if group.members.contains(&previous_owner) {
+            GROUP_ROLES.save(deps.storage, (group_id, &previous_owner), &GroupRole::Admin)?;
+        }
+        GROUP_ROLES.remove(deps.storage, (group_id, &info.sender));
+        
+        group.creator = info.sender.clone();
+        GROUPS.save(deps.storage, group_id, &group)?;
+        PENDING_OWNERS.remove(deps.storage, group_id);
+        
+        Ok(Response::new()
+            .add_attribute("action", "accept_ownership")
+            .add_attribute("group_id", group_id.to_string())
+            .add_event(Event::new("group_ownership_transferred")
+                .add_attribute("group_id", group_id.to_string())
+                .add_attribute("previous_owner", previous_owner)
+                .add_attribute("new_owner", info.sender)))
+    }
+
+    pub fn cancel_ownership_transfer(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        if info.sender != group.creator {
+            return Err(ContractError::Unauthorized {});
+        }
+        
+        let pending = PENDING_OWNERS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::NoPendingOwnership {})?;
+        PENDING_OWNERS.remove(deps.storage, group_id);
+        
+        Ok(Response::new()
+            .add_attribute("action", "cancel_ownership_transfer")
+            .add_attribute("group_id", group_id.to_string())
+            .add_event(Event::new("group_ownership_transfer_cancelled")
+                .add_attribute("group_id", group_id.to_string())
+                .add_attribute("owner", info.sender)
+                .add_attribute("new_owner", pending.new_owner)))
+    }
+
+    pub fn archive_group(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        // Everything has to be settled before the books are closed
+        if has_outstanding_debts(deps.storage, group_id) {
+            return Err(ContractError::OutstandingDebts {});
+        }
+        
+        group.status = GroupStatus::Archived;
+        GROUPS.save(deps.storage, group_id, &group)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "archive_group")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("archived_by", info.sender))
+    }
+
+    pub fn reopen_group(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        if group.status != GroupStatus::Archived {
+            return Err(ContractError::CustomError { 
+                val: "Only archived groups can be reopened".to_string() 
+            });
+        }
+        
+        group.status = GroupStatus::Active;
+        GROUPS.save(deps.storage, group_id, &group)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "reopen_group")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("reopened_by", info.sender))
+    }
+
+    pub fn remove_member(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        member: String,
+    ) -> Result<Response, ContractError> {
+        let member = deps.api.addr_validate(&member)?;
+        
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        if member == info.sender {
+            return Err(ContractError::CustomError { 
+                val: "Use LeaveGroup to leave a group".to_string() 
+            });
+        }
+        
+        // Nobody can remove the owner, and only the owner can remove an admin
+        match member_role(deps.storage, &group, &member)? {
+            None => return Err(ContractError::UserNotInGroup {}),
+            Some(GroupRole::Owner) => return Err(ContractError::Unauthorized {}),
+            Some(GroupRole::Admin) if info.sender != group.creator => {
+                return Err(ContractError::Unauthorized {});
+            }
+            Some(_) => {}
+        }
+        
+        // Expenses that could still become debts have to be settled one way or the other first
+        if has_open_expenses(deps.storage, group_id, &member)? {
+            return Err(ContractError::PendingExpenses {});
+        }
+        
+        group.members.retain(|existing| existing != member);
+        GROUP_ROLES.remove(deps.storage, (group_id, &member));
+        
+        // Open debts stay where they are so both sides can still settle them
+        let departed = has_member_debts(deps.storage, group_id, &member)?;
+        if departed {
+            group.departed_members.push(member.clone());
+        } else {
+            remove_user_group(deps.storage, &member, group_id)?;
+            let mut opted_in = SIMPLIFY_OPT_INS.may_load(deps.storage, group_id)?.unwrap_or_default();
+            opted_in.retain(|existing| existing != member);
+            SIMPLIFY_OPT_INS.save(deps.storage, group_id, &opted_in)?;
+        }
+        GROUPS.save(deps.storage, group_id, &group)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "remove_member")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("member", member)
+            .add_attribute("removed_by", info.sender)
+            .add_attribute("departed", departed.to_string()))
+    }
+
+    pub fn join_group(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        group_id: u64,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        // Check if the user is already a member
+        if group.members.contains(&info.sender) {
+            return Err(ContractError::UserAlreadyInGroup {});
+        }
+        
+        // An invite lets the user in regardless of the group's privacy
+        let invited = INVITES.has(deps.storage, (group_id, &info.sender));
+        if !invited {
+            match group.privacy {
+                GroupPrivacy::Open => {}
+                GroupPrivacy::InviteOnly => return Err(ContractError::NotInvited {}),
+                GroupPrivacy::ApprovalRequired => {
+                    // Park the request until an admin decides on it
+                    if JOIN_REQUESTS.has(deps.storage, (group_id, &info.sender)) {
+                        return Err(ContractError::JoinRequestPending {});
+                    }
+                    let request = JoinRequest {
+                        group_id,
+                        requester: info.sender.clone(),
+                        requested_at: env.block.time,
+                    };
+                    JOIN_REQUESTS.save(deps.storage, (group_id, &info.sender), &request)?;
+                    
+                    return Ok(Response::new()
+                        .add_attribute("action", "request_join")
+                        .add_attribute("group_id", group_id.to_string())
+                        .add_attribute("user", info.sender));
+                }
+            }
+        }
+        
+        add_member(deps.storage, &mut group, &info.sender)?;
+        if invited {
+            remove_invite(deps.storage, group_id, &info.sender)?;
+        }
+        
+        Ok(Response::new()
+            .add_attribute("action", "join_group")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("user", info.sender))
+    }
+
+    pub fn create_join_code(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        group_id: u64,
+        public_key: String,
+        expires_at: Option<Timestamp>,
+        max_uses: Option<u32>,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        // Normalize the key so lookups don't depend on letter case
+        let public_key = public_key.to_lowercase();
+        if !hex::decode(&public_key).is_ok_and(|bytes| is_public_key(&bytes)) {
+            return Err(ContractError::InvalidJoinCode { 
+                reason: "public_key must be a hex-encoded secp256k1 public key".to_string() 
+            });
+        }
+        if JOIN_CODES.has(deps.storage, &public_key) {
+            return Err(ContractError::InvalidJoinCode { 
+                reason: "code is already in use".to_string() 
+            });
+        }
+        if expires_at.is_some_and(|expires_at| expires_at <= env.block.time) {
+            return Err(ContractError::InvalidJoinCode { 
+                reason: "expiry must be in the future".to_string() 
+            });
+        }
+        if max_uses == Some(0) {
+            return Err(ContractError::InvalidJoinCode { 
+                reason: "max_uses must be greater than zero".to_string() 
+            });
+        }
+        
+        let code = JoinCode {
+            group_id,
+            public_key: public_key.clone(),
+            created_by: info.sender.clone(),
+            created_at: env.block.time,
+            expires_at,
+            max_uses,
+            uses: 0,
+        };
+        JOIN_CODES.save(deps.storage, &public_key, &code)?;
+        
+        let mut group_codes = GROUP_JOIN_CODES.may_load(deps.storage, group_id)?.unwrap_or_default();
+        group_codes.push(public_key.clone());
+        GROUP_JOIN_CODES.save(deps.storage, group_id, &group_codes)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "create_join_code")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("public_key", public_key))
+    }
+
+    pub fn revoke_join_code(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        public_key: String,
+    ) -> Result<Response, ContractError> {
+        let public_key = public_key.to_lowercase();
+        let code = JOIN_CODES.may_load(deps.storage, &public_key)?
+            .ok_or(ContractError::InvalidJoinCode { reason: "code not found".to_string() })?;
+        let group = GROUPS.may_load(deps.storage, code.group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        ensure_admin(deps.storage, &group, &info.sender)?;
+        
+        remove_join_code(deps.storage, &code)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "revoke_join_code")
+            .add_attribute("group_id", code.group_id.to_string())
+            .add_attribute("public_key", public_key))
+    }
+
+    pub fn join_group_with_code(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        public_key: String,
+        signature: String,
+    ) -> Result<Response, ContractError> {
+        let public_key = public_key.to_lowercase();
+        let mut join_code = JOIN_CODES.may_load(deps.storage, &public_key)?
+            .ok_or(ContractError::InvalidJoinCode { reason: "code not found".to_string() })?;
+        
+        // The signature covers the sender's own address, so seeing it on chain doesn't let anyone else join
+        let message_hash = Sha256::digest(info.sender.as_bytes());
+        let signed = match (hex::decode(&public_key), hex::decode(&signature)) {
+            (Ok(key), Ok(signature)) => deps.api.secp256k1_verify(&message_hash, &signature, &key).unwrap_or(false),
+            _ => false,
+        };
+        if !signed {
+            return Err(ContractError::InvalidJoinCode { reason: "signature does not match the code".to_string() });
+        }
+        
+        if join_code.expires_at.is_some_and(|expires_at| expires_at <= env.block.time) {
+            return Err(ContractError::InvalidJoinCode { reason: "code has expired".to_string() });
+        }
+        
+        let mut group = GROUPS.may_load(deps.storage, join_code.group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        ensure_active(&group)?;
+        
+        // A code works like an invite, whatever the group's privacy
+        add_member(deps.storage, &mut group, &info.sender)?;
+        if INVITES.has(deps.storage, (group.id, &info.sender)) {
+            remove_invite(deps.storage, group.id, &info.sender)?;
+        }
+        
+        // Use up the code, dropping it once it has no uses left
+        join_code.uses += 1;
+        if join_code.max_uses.is_some_and(|max_uses| join_code.uses >= max_uses) {
+            remove_join_code(deps.storage, &join_code)?;
+        } else {
+            JOIN_CODES.save(deps.storage, &public_key, &join_code)?;
+        }
+        
+        Ok(Response::new()
+            .add_attribute("action", "join_group")
+            .add_attribute("group_id", group.id.to_string())
+            .add_attribute("user", info.sender)
+            .add_attribute("public_key", public_key))
+    }
+
+    // Compressed (33 bytes) or uncompressed (65 bytes) SEC1 encoding
+    fn is_public_key(bytes: &[u8]) -> bool {
+        matches!((bytes.len(), bytes.first()), (33, Some(0x02 | 0x03)) | (65, Some(0x04)))
+    }
+
+    fn remove_join_code(storage: &mut dyn Storage, code: &JoinCode) -> StdResult<()> {
+        JOIN_CODES.remove(storage, &code.public_key);
+        
+        let mut group_codes = GROUP_JOIN_CODES.may_load(storage, code.group_id)?.unwrap_or_default();
+        group_codes.retain(|key| key != &code.public_key);
+        if group_codes.is_empty() {
+            GROUP_JOIN_CODES.remove(storage, code.group_id);
+        } else {
+            GROUP_JOIN_CODES.save(storage, code.group_id, &group_codes)?;
+        }
+        Ok(())
+    }
+
+    // Adds a new member to the group and to their list of groups
+    fn add_member(storage: &mut dyn Storage, group: &mut Group, member: &Addr) -> Result<(), ContractError> {
+        if group.members.contains(member) {
+            return Err(ContractError::UserAlreadyInGroup {});
+        }
+        
+        let max_group_members = CONFIG.load(storage)?.max_group_members;
+        if group.members.len() >= max_group_members as usize {
+            return Err(ContractError::CustomError { 
+                val: format!("Too many members. Maximum is {}", max_group_members)
+            });
+        }
+        
+        // Add the user to the group
+        group.members.push(member.clone());
+        
+        // A departed member coming back still has the group in their list
+        if group.departed_members.contains(member) {
+            group.departed_members.retain(|departed| departed != member);
+        } else {
+            let mut user_groups = USER_GROUPS.may_load(storage, member)?.unwrap_or_default();
+            user_groups.push(group.id);
+            USER_GROUPS.save(storage, member, &user_groups)?;
+        }
+        GROUPS.save(storage, group.id, group)?;
+        
+        // A pending request is answered by joining
+        JOIN_REQUESTS.remove(storage, (group.id, member));
+        Ok(())
+    }
+
+    fn remove_invite(storage: &mut dyn Storage, group_id: u64, invitee: &Addr) -> StdResult<()> {
+        INVITES.remove(storage, (group_id, invitee));
+        
+        let mut user_invites = USER_INVITES.may_load(storage, invitee)?.unwrap_or_default();
+        user_invites.retain(|&id| id != group_id);
+        if user_invites.is_empty() {
+            USER_INVITES.remove(storage, invitee);
+        } else {
+            USER_INVITES.save(storage, invitee, &user_invites)?;
+        }
+        Ok(())
+    }
+
+    pub fn leave_group(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        group_id: u64,
+    ) -> Result<Response, ContractError> {
+        // Check if the group exists
+        let mut group = GROUPS.may_load(deps.storage, group_id)?
+            .ok_or(ContractError::GroupNotFound {})?;
+        
+        // Check if the user is a member
+        if !group.members.contains(&info.sender) {
+            return Err(ContractError::UserNotInGroup {});
+        }
+        
+        // The owner can't leave the others without anyone in charge
+        if info.sender == group.creator && group.members.len() > 1 {
+            return Err(ContractError::CustomError { 
+                val: "The group owner cannot leave while other members remain; transfer ownership first".to_string() 
+            });
+        }
+        
+        // Check if user has any unsettled debts in this group
+        let has_debts = has_member_debts(deps.storage, group_id, &info.sender)?;
+        
+        // Cannot leave group with unsettled debts
+        if has_debts {
+            return Err(ContractError::CustomError { 
+                val: "Cannot leave group with unsettled debts".to_string() 
+            });
+        }
+        
+        // Nor while an expense could still turn into a debt for them
+        if has_open_expenses(deps.storage, group_id, &info.sender)? {
+            return Err(ContractError::PendingExpenses {});
+        }
+        
+        // Remove user from the group
+        group.members.retain(|member| member != info.sender);
+        GROUP_ROLES.remove(deps.storage, (group_id, &info.sender));
+        
+        // If group is now empty, close it. The group and its expenses stay queryable
+        // as history, while anything that would let someone back in is cleaned up.
+        if group.members.is_empty() {
+            group.status = GroupStatus::Closed;
+            GROUPS.save(deps.storage, group_id, &group)?;
+            
+            SIMPLIFY_OPT_INS.remove(deps.storage, group_id);
+            PENDING_OWNERS.remove(deps.storage, group_id);
+            let invitees: Vec<Addr> = INVITES
+                .prefix(group_id)
+                .keys(deps.storage, None, None, Order::Ascending)
+                .collect::<StdResult<_>>()?;
+            for invitee in invitees {
+                remove_invite(deps.storage, group_id, &invitee)?;
+            }
+            let requesters: Vec<Addr> = JOIN_REQUESTS
+                .prefix(group_id)
+                .keys(deps.storage, None, None, Order::Ascending)
+                .collect::<StdResult<_>>()?;
+            for requester in requesters {
+                JOIN_REQUESTS.remove(deps.storage, (group_id, &requester));
+            }
+            for public_key in GROUP_JOIN_CODES.may_load(deps.storage, group_id)?.unwrap_or_default() {
+                JOIN_CODES.remove(deps.storage, &public_key);
+            }
+            GROUP_JOIN_CODES.remove(deps.storage, group_id);
+        } else {
+            // Otherwise save the updated group
+            GROUPS.save(deps.storage, group_id, &group)?;
+            
+            let mut opted_in = SIMPLIFY_OPT_INS.may_load(deps.storage, group_id)?.unwrap_or_default();
+            opted_in.retain(|member| member != info.sender);
+            SIMPLIFY_OPT_INS.save(deps.storage, group_id, &opted_in)?;
+        }
+        
+        // Update the user's groups
+        remove_user_group(deps.storage, &info.sender, group_id)?;
+        
+        Ok(Response::new()
+            .add_attribute("action", "leave_group")
+            .add_attribute("group_id", group_id.to_string())
+            .add_attribute("user", info.sender))
+    }
+
+    fn validate_group_name(config: &Config, name: &str) -> Result<(), ContractError> {
+        if name.trim().is_empty() {
+            return Err(ContractError::InvalidGroupName { 
+                reason: "Group name cannot be empty".to_string() 
+            });
+        }
+        
+        if name.len() > config.max_group_name_length as usize {
+            return Err(ContractError::InvalidGroupName { 
+                reason: format!("Group name exceeds maximum length of {}", config.max_group_name_length) 
+            });
+        }
+        Ok(())
+    }
+
+    fn validate_metadata(field: &str, value: &str, max_length: usize) -> Result<(), ContractError> {
+        if value.len() > max_length {
+            return Err(ContractError::InvalidGroupMetadata { 
+                reason: format!("{} exceeds maximum length of {}", field, max_length) 
+            });
+        }
+        Ok(())
+    }
+
+    // Checks a native denom against the Cosmos SDK rules: a letter followed by
+    // 2 to 127 letters, digits or one of `/:._-` (this covers IBC denoms)
+    pub fn validate_denom(denom: &str) -> Result<(), ContractError> {
+        let invalid = |reason: &str| ContractError::InvalidDenom { 
+            denom: denom.to_string(),
+            reason: reason.to_string(),
+        };
+        
+        if denom.len() < 3 || denom.len() > 128 {
+            return Err(invalid("must be between 3 and 128 characters"));
+        }
+        if !denom.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err(invalid("must start with a letter"));
+        }
+        if !denom.chars().all(|c| c.is_ascii_alphanumeric() || "/:._-".contains(c)) {
+            return Err(invalid("contains invalid characters"));
+        }
+        Ok(())
+    }
+
+    fn has_outstanding_debts(storage: &dyn Storage, group_id: u64) -> bool {
+        DEBTS
+            .sub_prefix(group_id)
+            .keys(storage, None, None, Order::Ascending)
+            .next()
+            .is_some()
+    }
+
+    // Whether the user owes or is owed anything in the group
+    fn has_member_debts(storage: &dyn Storage, group_id: u64, user: &Addr) -> StdResult<bool> {
+        for key in DEBTS.sub_prefix(group_id).keys(storage, None, None, Order::Ascending) {
+            let (debtor, creditor) = key?;
+            if debtor == user || creditor == user {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Whether the user is involved in an expense that could still change their debts:
+    // one that isn't active yet, or one they have an open dispute on
+    fn has_open_expenses(storage: &dyn Storage, group_id: u64, user: &Addr) -> StdResult<bool> {
+        for id in OPEN_EXPENSES.prefix(group_id).keys(storage, None, None, Order::Ascending) {
+            let expense = EXPENSES.load(storage, id?)?;
+            let involved = expense.paid_by == *user
+                || expense.payers.iter().any(|contribution| contribution.payer == *user)
+                || expense.shares.iter().any(|share| share.member == *user && !share.amount.is_zero());
+            if (expense.status != ExpenseStatus::Active && involved) || is_disputing(&expense, user) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Members plus departed members, i.e. everyone who can hold debts in the group
+    pub fn participants(group: &Group) -> impl Iterator<Item = &Addr> {
+        group.members.iter().chain(&group.departed_members)
+    }
+
+    fn is_participant(group: &Group, user: &Addr) -> bool {
+        group.members.contains(user) || group.departed_members.contains(user)
+    }
+
+    // Drops departed members whose debts are all settled
+    fn release_departed_members(storage: &mut dyn Storage, mut group: Group) -> StdResult<()> {
+        let mut released = vec![];
+        for departed in &group.departed_members {
+            if !has_member_debts(storage, group.id, departed)? {
+                released.push(departed.clone());
+            }
+        }
+        if released.is_empty() {
+            return Ok(());
+        }
+        
+        group.departed_members.retain(|departed| !released.contains(departed));
+        GROUPS.save(storage, group.id, &group)?;
+        
+        let mut opted_in = SIMPLIFY_OPT_INS.may_load(storage, group.id)?.unwrap_or_default();
+        opted_in.retain(|member| !released.contains(member));
+        SIMPLIFY_OPT_INS.save(storage, group.id, &opted_in)?;
+        for departed in &released {
+            remove_user_group(storage, departed, group.id)?;
+        }
+        Ok(())
+    }
+
+    fn remove_user_group(storage: &mut dyn Storage, user: &Addr, group_id: u64) -> StdResult<()> {
+        let user_groups = USER_GROUPS.may_load(storage, user)?.unwrap_or_default();
+        let updated_user_groups: Vec<u64> = user_groups.into_iter()
+            .filter(|&id| id != group_id)
+            .collect();
+        
+        if updated_user_groups.is_empty() {
+            USER_GROUPS.remove(storage, user);
+        } else {
+            USER_GROUPS.save(storage, user, &updated_user_groups)?;
+        }
+        Ok(())
+    }
+
+    // The user-supplied parts of an expense, shared by AddExpense and EditExpense
+    pub struct ExpenseDetails {
+        pub description: String,
+        pub amount: Uint128,
+        pub split_between: Vec<String>, // Defaults to all group members if empty
+        pub split_mode: Option<SplitMode>,
+        pub category: Option<String>,
+        pub tags: Vec<String>,
+        pub occurred_at: Option<Timestamp>, // Defaults to the recording time
+        pub payers: Option<Vec<Payer>>,     // Defaults to paid_by paying the full amount
+    }
+
+    fn validate_expense_details(storage: &dyn Storage, details: &ExpenseDetails) -> Result<(), ContractError> {
+        let max_length = CONFIG.load(storage)?.max_expense_description_length;
+        
+        // Validate expense description
+        if details.description.trim().is_empty() {
+            return Err(ContractError::InvalidExpenseDescription { 
+                reason: "Expense description cannot be empty".to_string() 
+            });
+        }
+        
+        if details.description.len() > max_length as usize {
+            return Err(ContractError::InvalidExpenseDescription { 
+                reason: format!("Description exceeds maximum length of {}", max_length) 
+            });
+        }
+        
+        // Validate amount
+        if details.amount.is_zero() {
+            return Err(ContractError::InvalidAmount { 
+                reason: "Amount must be greater than zero".to_string() 
+            });
+        }
+        
+        // Validate tags
+        if details.tags.len() > MAX_EXPENSE_TAGS {
+            return Err(ContractError::InvalidExpenseTags { 
+                reason: format!("At most {} tags are allowed", MAX_EXPENSE_TAGS) 
+            });
+        }
+        for tag in &details.tags {
+            if tag.trim().is_empty() || tag.len() > MAX_TAG_LENGTH {
+                return Err(ContractError::InvalidExpenseTags { 
+                    reason: format!("Tags must be between 1 and {} characters", MAX_TAG_LENGTH) 
+                });
+            }
+        }
+        
+        Ok(())
+    }
+
+    // Expense dates can be backdated up to a year, and set a day ahead to allow for time zones
+    fn validate_expense_date(occurred_at: Timestamp, env: &Env) -> Result<(), ContractError> {
+        let now = env.block.time.seconds();
+        if occurred_at.seconds() < now.saturating_sub(MAX_EXPENSE_AGE_SECONDS) {
+            return Err(ContractError::InvalidExpenseDate { 
+                reason: format!("Expenses can't be dated more than {} days in the past", MAX_EXPENSE_AGE_SECONDS / 86_400) 
+            });
+        }
+        if occurred_at.seconds() > now.saturating_add(MAX_EXPENSE_LEAD_SECONDS) {
+            return Err(ContractError::InvalidExpenseDate { 
+                reason: format!("Expenses can't be dated more than {} hours in the future", MAX_EXPENSE_LEAD_SECONDS / 3_600) 
+            });
+        }
+        Ok(())
+    }
+
+    // Resolves the split members and shares for an expense paid by `paid_by`
+    fn build_expense(
+        deps: Deps,
+        group: &Group,
+        id: u64,
+        paid_by: Addr,
+        timestamp: Timestamp,
+        details: ExpenseDetails,
+    ) -> Result<Expense, ContractError> {
+        let ExpenseDetails { description, amount, split_between, split_mode, category, mut tags, occurred_at, payers } = details;
+        let split_mode = split_mode.unwrap_or(SplitMode::Equal {});
+        
+        // Categories come from the group's own list
+        if let Some(category) = &category {
+            if !group.categories.contains(category) {
+                return Err(ContractError::UnknownCategory { category: category.clone() });
+            }
+        }
+        tags.sort();
+        tags.dedup();
+        
+        let payers = match payers {
+            Some(payers) => validate_payers(deps, group, &paid_by, amount, payers)?,
+            None => vec![Contribution { payer: paid_by.clone(), amount }],
+        };
+        
+        // Determine who shares this expense
+        let split_members: Vec<Addr> = if split_between.is_empty() {
+            // Weighted splits need to know which value belongs to whom
+            if split_mode != (SplitMode::Equal {}) {
+                return Err(ContractError::InvalidSplit { 
+                    reason: "split_between must list members for a non-equal split".to_string() 
+                });
+            }
+            
+            // If no specific members are provided, all group members except viewers share the expense
+            let mut split_members = Vec::with_capacity(group.members.len());
+            for member in &group.members {
+                if member_role(deps.storage, group, member)? != Some(GroupRole::Viewer) {
+                    split_members.push(member.clone());
+                }
+            }
+            split_members
+        } else {
+            // Otherwise, validate the provided addresses
+            let mut validated_split = Vec::with_capacity(split_between.len());
+            
+            for member in split_between {
+                let addr = deps.api.addr_validate(&member)?;
+                
+                // Check if the address is a member of the group who can be charged
+                match member_role(deps.storage, group, &addr)? {
+                    None => return Err(ContractError::UserNotInGroup {}),
+                    Some(GroupRole::Viewer) => return Err(ContractError::InvalidSplit { 
+                        reason: format!("{} is a viewer and can't share expenses", addr) 
+                    }),
+                    Some(_) => {}
+                }
+                
+                if !validated_split.contains(&addr) {
+                    validated_split.push(addr);
+                } else if split_mode != (SplitMode::Equal {}) {
+                    // A duplicate would shift every following value onto the wrong member
+                    return Err(ContractError::InvalidSplit { 
+                        reason: format!("{} is listed more than once", addr) 
+                    });
+                }
+            }
+            
+            validated_split
+        };
+        
+        // Work out how much each member is responsible for
+        let mut share_amounts = compute_shares(amount, split_members.len(), &split_mode)?;
+        allocate_remainder(
+            amount,
+            &mut share_amounts,
+            &split_members,
+            &split_mode,
+            &paid_by,
+            group.remainder_policy,
+            id,
+        );
+        let shares: Vec<Share> = split_members
+            .iter()
+            .zip(share_amounts)
+            .map(|(member, amount)| Share { member: member.clone(), amount, settled: false })
+            .collect();
+        
+        Ok(Expense {
+            id,
+            group_id: group.id,
+            description,
+            amount,
+            paid_by,
+            split_between: split_members,
+            split_mode,
+            shares,
+            payers,
+            timestamp,
+            settled: false,
+            category,
+            tags,
+            occurred_at: occurred_at.unwrap_or(timestamp),
+            status: ExpenseStatus::Active,
+            recorded_by: None,
+            confirmations: vec![],
+            approvals: vec![],
+            disputes: vec![],
+        })
+    }
+
+    // Whether a member has an open dispute on an expense
+    fn is_disputing(expense: &Expense, member: &Addr) -> bool {
+        expense.disputes.iter().any(|dispute| dispute.member == *member && dispute.outcome.is_none())
+    }
+
+    // Whether any of what the expense charges `member` has been paid off
+    fn has_paid_towards(storage: &dyn Storage, expense: &Expense, member: &Addr) -> StdResult<bool> {
+        for creditor in CHARGED_TO.may_load(storage, (expense.id, member))?.unwrap_or_default() {
+            let key = ((expense.group_id, member), &creditor, expense.id);
+            if PAID_CHARGES.has(storage, key) {
+                return Ok(true);
+            }
+            if UNPAID_CHARGES.may_load(storage, key)?.is_some_and(|charge| !charge.paid.is_zero()) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Closes the open disputes on an expense, or only `member`'s. Returns whose disputes were closed.
+    fn close_disputes(
+        expense: &mut Expense,
+        outcome: DisputeOutcome,
+        resolved_by: &Addr,
+        env: &Env,
+        member: Option<&Addr>,
+    ) -> Vec<Addr> {
+        let mut closed = Vec::new();
+        for dispute in expense.disputes.iter_mut() {
+            if dispute.outcome.is_some() || member.is_some_and(|member| dispute.member != *member) {
+                continue;
+            }
+            dispute.outcome = Some(outcome);
+            dispute.resolved_by = Some(resolved_by.clone());
+            dispute.resolved_at = Some(env.block.time);
+            closed.push(dispute.member.clone());
+        }
+        closed
+    }
+
+    // Shares held out of the group's debts by open disputes
+    pub fn frozen_shares(expense: &Expense) -> Vec<Share> {
+        if expense.status != ExpenseStatus::Active {
+            return vec![];
+        }
+        expense.shares
+            .iter()
+            .filter(|share| !share.amount.is_zero() && is_disputing(expense, &share.member))
+            .cloned()
+            .collect()
+    }
+
+    // The payments to use when an expense changes without new ones being given:
+    // shared payments are kept as they were, a sole payer simply paid the new amount
+    fn kept_payers(previous: &Expense, payers: Option<Vec<Payer>>) -> Option<Vec<Payer>> {
+        if payers.is_none() && previous.payers.len() > 1 {
+            return Some(previous.payers.iter()
+                .map(|contribution| Payer { address: contribution.payer.to_string(), amount: contribution.amount })
+                .collect());
+        }
+        payers
+    }
+
+    // Builds the new version of an expense, keeping its identity, original payer and history
+    fn rebuild_expense(
+        deps: Deps,
+        group: &Group,
+        previous: &Expense,
+        details: ExpenseDetails,
+    ) -> Result<Expense, ContractError> {
+        let mut expense = build_expense(
+            deps,
+            group,
+            previous.id,
+            previous.paid_by.clone(),
+            previous.timestamp,
+            details,
+        )?;
+        expense.settled = previous.settled;
+        expense.recorded_by = previous.recorded_by.clone();
+        expense.disputes = previous.disputes.clone();
+        Ok(expense)
+    }
+
+    // Saves the new version of an expense in place of `previous`, swapping their debts
+    fn replace_expense(
+        storage: &mut dyn Storage,
+        env: &Env,
+        sender: &Addr,
+        previous: Expense,
+        expense: &Expense,
+    ) -> StdResult<()> {
+        EXPENSES.save(storage, expense.id, expense)?;
+        unindex_expense(storage, &previous);
+        index_expense(storage, expense)?;
+        track_open_expense(storage, expense)?;
+        reverse_expense_debts(storage, &previous)?;
+        apply_expense_debts(storage, expense)?;
+        record_revision(storage, previous, ExpenseChange::Edited, sender, env)
+    }
+
+    // Undoes everything an expense contributed to the group's debts and drops it from the group
+    fn remove_expense(storage: &mut dyn Storage, expense: &Expense) -> StdResult<()> {
+        reverse_expense_debts(storage, expense)?;
+        EXPENSES.remove(storage, expense.id);
+        unindex_expense(storage, expense);
+        OPEN_EXPENSES.remove(storage, (expense.group_id, expense.id));
+        
+        let group_expenses = GROUP_EXPENSES
+            .may_load(storage, expense.group_id)?
+            .unwrap_or_default();
+        let updated_group_expenses: Vec<u64> = group_expenses.into_iter()
+            .filter(|&expense_id| expense_id != expense.id)
+            .collect();
+        GROUP_EXPENSES.save(storage, expense.group_id, &updated_group_expenses)
+    }
+
+    // Adds an expense to the date indexes queries page through
+    pub fn index_expense(storage: &mut dyn Storage, expense: &Expense) -> StdResult<()> {
+        EXPENSES_BY_RECORDED.save(storage, (expense.group_id, expense.timestamp.nanos(), expense.id), &Empty {})?;
+        EXPENSES_BY_OCCURRED.save(storage, (expense.group_id, expense.occurred_at.nanos(), expense.id), &Empty {})
+    }
+
+    fn unindex_expense(storage: &mut dyn Storage, expense: &Expense) {
+        EXPENSES_BY_RECORDED.remove(storage, (expense.group_id, expense.timestamp.nanos(), expense.id));
+        EXPENSES_BY_OCCURRED.remove(storage, (expense.group_id, expense.occurred_at.nanos(), expense.id));
+    }
+
+    // Keeps the index of expenses waiting on someone in step with the expense
+    fn track_open_expense(storage: &mut dyn Storage, expense: &Expense) -> StdResult<()> {
+        let open = expense.status != ExpenseStatus::Active 
+            || expense.disputes.iter().any(|dispute| dispute.outcome.is_none());
+        if open {
+            OPEN_EXPENSES.save(storage, (expense.group_id, expense.id), &Empty {})
+        } else {
+            OPEN_EXPENSES.remove(storage, (expense.group_id, expense.id));
+            Ok(())
+        }
+    }
+
+    // The payers who haven't confirmed what a pending expense credits them with yet
+    pub fn unconfirmed_payers(expense: &Expense) -> Vec<Addr> {
+        expense.payers
+            .iter()
+            .filter(|contribution| !expense.confirmations.contains(&contribution.payer))
+            .map(|contribution| contribution.payer.clone())
+            .collect()
+    }
+
+    // The members who are asked to approve an expense: everyone it charges, except the payer
+    pub fn approvers(expense: &Expense) -> Vec<Addr> {
+        expense.shares
+            .iter()
+            .filter(|share| !share.amount.is_zero() && share.member != expense.paid_by)
+            .map(|share| share.member.clone())
+            .collect()
+    }
+
+    // Whether an expense that isn't waiting for its payer has enough approvals to count
+    fn approval_status(group: &Group, expense: &Expense) -> ExpenseStatus {
+        let approvers = approvers(expense);
+        let required = match group.approval_policy {
+            ApprovalPolicy::None => 0,
+            ApprovalPolicy::AllParticipants => approvers.len(),
+            ApprovalPolicy::Quorum { basis_points } => 
+                (approvers.len() * basis_points as usize).div_ceil(TOTAL_BASIS_POINTS as usize),
+        };
+        let approved = approvers.iter().filter(|member| expense.approvals.contains(member)).count();
+        if approved >= required {
+            ExpenseStatus::Active
+        } else {
+            ExpenseStatus::PendingApproval
+        }
+    }
+
+    // Checks that the contributions are by distinct group members, include `paid_by` and add up to `amount`
+    fn validate_payers(
+        deps: Deps,
+        group: &Group,
+        paid_by: &Addr,
+        amount: Uint128,
+        payers: Vec<Payer>,
+    ) -> Result<Vec<Contribution>, ContractError> {
+        let mut contributions: Vec<Contribution> = Vec::with_capacity(payers.len());
+        let mut total = Uint128::zero();
+        for payer in payers {
+            let addr = deps.api.addr_validate(&payer.address)?;
+            if !group.members.contains(&addr) {
+                return Err(ContractError::UserNotInGroup {});
+            }
+            if contributions.iter().any(|contribution| contribution.payer == addr) {
+                return Err(ContractError::InvalidPayers { 
+                    reason: format!("{} is listed more than once", addr) 
+                });
+            }
+            if payer.amount.is_zero() {
+                return Err(ContractError::InvalidPayers { 
+                    reason: format!("{} must pay more than zero", addr) 
+                });
+            }
+            total = total.checked_add(payer.amount).map_err(StdError::overflow)?;
+            contributions.push(Contribution { payer: addr, amount: payer.amount });
+        }
+        
+        if !contributions.iter().any(|contribution| contribution.payer == *paid_by) {
+            return Err(ContractError::InvalidPayers { 
+                reason: format!("{} must be one of the payers", paid_by) 
+            });
+        }
+        if total != amount {
+            return Err(ContractError::InvalidPayers { 
+                reason: format!("Payments sum to {} but the expense is {}", total, amount) 
+            });
+        }
+        Ok(contributions)
+    }
+
+    // Looks up what a user may do in a group, or None if they aren't a member
+    pub fn member_role(storage: &dyn Storage, group: &Group, user: &Addr) -> StdResult<Option<GroupRole>> {
+        if !group.members.contains(user) {
+            return Ok(None);
+        }
+        if user == group.creator {
+            return Ok(Some(GroupRole::Owner));
+        }
+        Ok(Some(GROUP_ROLES.may_load(storage, (group.id, user))?.unwrap_or(GroupRole::Member)))
+    }
+
+    // Rejects changes to groups that are archived or closed
+    fn ensure_active(group: &Group) -> Result<(), ContractError> {
+        match group.status {
+            GroupStatus::Active => Ok(()),
+            GroupStatus::Archived => Err(ContractError::GroupArchived {}),
+            GroupStatus::Closed => Err(ContractError::GroupClosed {}),
+        }
+    }
+
+    fn ensure_admin(storage: &dyn Storage, group: &Group, sender: &Addr) -> Result<(), ContractError> {
+        match member_role(storage, group, sender)? {
+            Some(GroupRole::Owner | GroupRole::Admin) => Ok(()),
+            Some(_) => Err(ContractError::Unauthorized {}),
+            None => Err(ContractError::UserNotInGroup {}),
+        }
+    }
+
+    fn ensure_can_post(storage: &dyn Storage, group: &Group, sender: &Addr) -> Result<(), ContractError> {
+        match member_role(storage, group, sender)? {
+            Some(GroupRole::Viewer) => Err(ContractError::Unauthorized {}),
+            Some(_) => Ok(()),
+            None => Err(ContractError::UserNotInGroup {}),
+        }
+    }
+
+    fn ensure_can_modify_expense(
+        storage: &dyn Storage,
+        group: &Group,
+        expense: &Expense,
+        sender: &Addr,
+    ) -> Result<(), ContractError> {
+        match member_role(storage, group, sender)? {
+            Some(GroupRole::Owner | GroupRole::Admin) => Ok(()),
+            Some(GroupRole::Member) if *sender == expense.paid_by || expense.recorded_by.as_ref() == Some(sender) => Ok(()),
+            _ => Err(ContractError::Unauthorized {}),
+        }
+    }
+
+    // Works out what each member owes each payer for an expense: every share is divided
+    // among the payers in proportion to what they paid. Returns (debtor, creditor, amount)
+    // edges; the part of a payer's own share they paid themselves is left out.
+    pub fn expense_debts(expense: &Expense) -> StdResult<Vec<(Addr, Addr, Uint128)>> {
+        let total: Uint128 = expense.payers.iter().map(|contribution| contribution.amount).sum();
+        
+        // Round every portion down first
+        let mut portions: Vec<Vec<Uint128>> = Vec::with_capacity(expense.shares.len());
+        for share in &expense.shares {
+            let row = expense.payers
+                .iter()
+                .map(|contribution| share.amount.checked_multiply_ratio(contribution.amount, total))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| StdError::generic_err(e.to_string()))?;
+            portions.push(row);
+        }
+        
+        // Then hand each share's leftover units to payers who haven't been credited their full
+        // payment yet, so both every share and every payment are accounted for exactly
+        let mut shortfalls: Vec<Uint128> = expense.payers
+            .iter()
+            .enumerate()
+            .map(|(j, contribution)| contribution.amount - portions.iter().map(|row| row[j]).sum::<Uint128>())
+            .collect();
+        for (share, row) in expense.shares.iter().zip(portions.iter_mut()) {
+            let mut leftover = share.amount - row.iter().sum::<Uint128>();
+            for (portion, shortfall) in row.iter_mut().zip(shortfalls.iter_mut()) {
+                let units = leftover.min(*shortfall);
+                *portion += units;
+                *shortfall -= units;
+                leftover -= units;
+            }
+        }
+        
+        let mut debts = Vec::new();
+        for (share, row) in expense.shares.iter().zip(portions) {
+            // Disputed shares stay out of the debts until the dispute is resolved
+            if is_disputing(expense, &share.member) {
+                continue;
+            }
+            for (contribution, portion) in expense.payers.iter().zip(row) {
+                // Skip what members paid for themselves (they don't owe themselves)
+                if contribution.payer != share.member && !portion.is_zero() {
+                    debts.push((share.member.clone(), contribution.payer.clone(), portion));
+                }
+            }
+        }
+        Ok(debts)
+    }
+
+    // Hands what has been paid on each debt edge to the charges behind it, oldest expense first,
+    // and marks the shares that are now paid off. Whatever a member no longer owes a creditor
+    // of what the expenses charged them, whether through settlements or netting, counts as
+    // paid. Only the unpaid charges of the given edges are walked.
+    pub fn settle_edges(storage: &mut dyn Storage, group_id: u64, edges: &[(Addr, Addr)]) -> StdResult<()> {
+        // Netting means a change on one edge can pay off the opposite one
+        let edges: BTreeSet<(Addr, Addr)> = edges
+            .iter()
+            .flat_map(|(debtor, creditor)| [(debtor.clone(), creditor.clone()), (creditor.clone(), debtor.clone())])
+            .collect();
+        let mut touched = BTreeSet::new();
+        for (debtor, creditor) in &edges {
+            touched.extend(settle_edge(storage, group_id, debtor, creditor)?);
+        }
+        for id in touched {
+            refresh_settled(storage, id)?;
+        }
+        Ok(())
+    }
+
+    // Spreads what `debtor` has paid `creditor` over the charges between them.
+    // Returns the expenses whose charges on this edge were paid off or reopened.
+    fn settle_edge(storage: &mut dyn Storage, group_id: u64, debtor: &Addr, creditor: &Addr) -> StdResult<Vec<u64>> {
+        let owed = DEBTS.may_load(storage, (group_id, debtor, creditor))?.unwrap_or_default();
+        let edge = ((group_id, debtor), creditor);
+        let mut charges: Vec<(u64, Charge)> = UNPAID_CHARGES
+            .prefix(edge)
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        
+        // A debt that came back (e.g. a netted expense was removed) reopens the latest paid charges
+        let mut reopen = owed.saturating_sub(charges.iter().map(|(_, charge)| charge.amount).sum());
+        if !reopen.is_zero() {
+            for item in PAID_CHARGES.prefix(edge).range(storage, None, None, Order::Descending) {
+                let (id, amount) = item?;
+                charges.push((id, Charge { amount, paid: amount }));
+                reopen = reopen.saturating_sub(amount);
+                if reopen.is_zero() {
+                    break;
+                }
+            }
+            charges.sort_by_key(|(id, _)| *id);
+        }
+        
+        let total: Uint128 = charges.iter().map(|(_, charge)| charge.amount).sum();
+        let mut available = total.saturating_sub(owed);
+        let mut touched = Vec::new();
+        for (id, charge) in charges {
+            let key = (edge.0, edge.1, id);
+            let was_paid = charge.paid == charge.amount;
+            let paid = charge.amount.min(available);
+            available -= paid;
+            if paid == charge.amount {
+                if !was_paid {
+                    UNPAID_CHARGES.remove(storage, key);
+                    PAID_CHARGES.save(storage, key, &charge.amount)?;
+                    touched.push(id);
+                }
+            } else if was_paid || paid != charge.paid {
+                PAID_CHARGES.remove(storage, key);
+                UNPAID_CHARGES.save(storage, key, &Charge { amount: charge.amount, paid })?;
+                if was_paid {
+                    touched.push(id);
+                }
+            }
+        }
+        Ok(touched)
+    }
+
+    // Marks each share of an expense settled once everything it charges has been paid off
+    pub fn refresh_settled(storage: &mut dyn Storage, id: u64) -> StdResult<()> {
+        let Some(mut expense) = EXPENSES.may_load(storage, id)? else {
+            return Ok(());
+        };
+        let frozen = frozen_shares(&expense);
+        let mut shares = expense.shares.clone();
+        for share in shares.iter_mut() {
+            let creditors = CHARGED_TO.may_load(storage, (id, &share.member))?.unwrap_or_default();
+            share.settled = expense.status == ExpenseStatus::Active
+                && !frozen.iter().any(|f| f.member == share.member)
+                && !creditors.iter().any(|creditor| {
+                    UNPAID_CHARGES.has(storage, ((expense.group_id, &share.member), creditor, id))
+                });
+        }
+        let settled = shares.iter().all(|share| share.settled);
+        if shares != expense.shares || settled != expense.settled {
+            expense.shares = shares;
+            expense.settled = settled;
+            EXPENSES.save(storage, expense.id, &expense)?;
+        }
+        Ok(())
+    }
+
+    // Moves each debtor's unpaid charges onto the debts simplification left them with, oldest
+    // expense first, so a share stays unsettled until those are paid. Whatever a debtor no
+    // longer owes anyone was netted away and counts as paid.
+    fn move_charges(
+        storage: &mut dyn Storage,
+        group_id: u64,
+        debtors: &BTreeSet<Addr>,
+        transfers: &[Debt],
+    ) -> StdResult<()> {
+        let mut touched: BTreeSet<(u64, Addr)> = BTreeSet::new();
+        for debtor in debtors {
+            let mut charges: Vec<((Addr, u64), Charge)> = UNPAID_CHARGES
+                .sub_prefix((group_id, debtor))
+                .range(storage, None, None, Order::Ascending)
+                .collect::<StdResult<_>>()?;
+            charges.sort_by(|((a, a_id), _), ((b, b_id), _)| (a_id, a).cmp(&(b_id, b)));
+            
+            let mut debts: Vec<(Addr, Uint128)> = transfers
+                .iter()
+                .filter(|transfer| transfer.debtor == *debtor)
+                .map(|transfer| (transfer.creditor.clone(), transfer.amount))
+                .collect();
+            let unpaid: Uint128 = charges.iter().map(|(_, charge)| charge.amount - charge.paid).sum();
+            let owed: Uint128 = debts.iter().map(|(_, amount)| *amount).sum();
+            let mut netted = unpaid.saturating_sub(owed);
+            
+            // Take them all off first: parts of a share with several payers can land on each other's edges
+            for ((creditor, id), _) in &charges {
+                UNPAID_CHARGES.remove(storage, ((group_id, debtor), creditor, *id));
+            }
+            let mut next = 0;
+            for ((creditor, id), charge) in charges {
+                let mut unpaid = charge.amount - charge.paid;
+                let paid_off = unpaid.min(netted);
+                netted -= paid_off;
+                unpaid -= paid_off;
+                
+                // The rest follows the debt to its new creditors
+                let mut parts: Vec<(Addr, Uint128)> = vec![];
+                while !unpaid.is_zero() && next < debts.len() {
+                    let (new_creditor, room) = &mut debts[next];
+                    let part = unpaid.min(*room);
+                    *room -= part;
+                    unpaid -= part;
+                    parts.push((new_creditor.clone(), part));
+                    if room.is_zero() {
+                        next += 1;
+                    }
+                }
+                
+                // What's paid stays behind on the old edge
+                let key = ((group_id, debtor), &creditor, id);
+                let paid = charge.amount - parts.iter().map(|(_, part)| *part).sum::<Uint128>();
+                if !paid.is_zero() {
+                    match UNPAID_CHARGES.may_load(storage, key)? {
+                        Some(moved) => {
+                            let charge = Charge { amount: moved.amount + paid, paid: moved.paid + paid };
+                            UNPAID_CHARGES.save(storage, key, &charge)?;
+                        }
+                        None => {
+                            let earlier = PAID_CHARGES.may_load(storage, key)?.unwrap_or_default();
+                            PAID_CHARGES.save(storage, key, &(earlier + paid))?;
+                        }
+                    }
+                }
+                for (new_creditor, part) in parts {
+                    record_charge(storage, group_id, id, debtor, &new_creditor, part)?;
+                }
+                touched.insert((id, debtor.clone()));
+            }
+        }
+        
+        // Forget the edges a share no longer has charges on
+        for (id, debtor) in &touched {
+            let creditors: Vec<Addr> = CHARGED_TO.may_load(storage, (*id, debtor))?
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|creditor| {
+                    let key = ((group_id, debtor), creditor, *id);
+                    UNPAID_CHARGES.has(storage, key) || PAID_CHARGES.has(storage, key)
+                })
+                .collect();
+            if creditors.is_empty() {
+                CHARGED_TO.remove(storage, (*id, debtor));
+            } else {
+                CHARGED_TO.save(storage, (*id, debtor), &creditors)?;
+            }
+        }
+        let ids: BTreeSet<u64> = touched.into_iter().map(|(id, _)| id).collect();
+        for id in ids {
+            refresh_settled(storage, id)?;
+        }
+        Ok(())
+    }
+
+    // Adds `amount` to what an expense charges `debtor` on their edge to `creditor`,
+    // keeping whatever was already paid of it
+    pub fn record_charge(
+        storage: &mut dyn Storage,
+        group_id: u64,
+        expense_id: u64,
+        debtor: &Addr,
+        creditor: &Addr,
+        amount: Uint128,
+    ) -> StdResult<()> {
+        let key = ((group_id, debtor), creditor, expense_id);
+        let mut charge = match UNPAID_CHARGES.may_load(storage, key)? {
+            Some(charge) => charge,
+            None => {
+                let paid = PAID_CHARGES.may_load(storage, key)?.unwrap_or_default();
+                PAID_CHARGES.remove(storage, key);
+                Charge { amount: paid, paid }
+            }
+        };
+        charge.amount += amount;
+        UNPAID_CHARGES.save(storage, key, &charge)?;
+        
+        let mut creditors = CHARGED_TO.may_load(storage, (expense_id, debtor))?.unwrap_or_default();
+        if !creditors.contains(creditor) {
+            creditors.push(creditor.clone());
+            CHARGED_TO.save(storage, (expense_id, debtor), &creditors)?;
+        }
+        Ok(())
+    }
+
+    // Adds each member's share to what they owe the payers. Only active expenses count towards the debts.
+    fn apply_expense_debts(storage: &mut dyn Storage, expense: &Expense) -> StdResult<()> {
+        if expense.status != ExpenseStatus::Active {
+            return Ok(());
+        }
+        let debts = expense_debts(expense)?;
+        for (debtor, creditor, amount) in &debts {
+            add_debt(storage, expense.group_id, debtor, creditor, *amount)?;
+            record_charge(storage, expense.group_id, expense.id, debtor, creditor, *amount)?;
+        }
+        let edges: Vec<(Addr, Addr)> = debts.into_iter().map(|(debtor, creditor, _)| (debtor, creditor)).collect();
+        settle_edges(storage, expense.group_id, &edges)?;
+        refresh_settled(storage, expense.id)
+    }
+
+    // Takes back each member's share from what they owe the payers
+    fn reverse_expense_debts(storage: &mut dyn Storage, expense: &Expense) -> StdResult<()> {
+        if expense.status != ExpenseStatus::Active {
+            return Ok(());
+        }
+        let debts = expense_debts(expense)?;
+        let mut edges: Vec<(Addr, Addr)> = Vec::with_capacity(debts.len());
+        for (debtor, creditor, amount) in debts {
+            // Reversing is the same as the payer now owing the member that portion
+            reduce_debt(storage, expense.group_id, &debtor, &creditor, amount)?;
+            edges.push((debtor, creditor));
+        }
+        
+        // Drop the charges wherever they are now
+        for share in &expense.shares {
+            for creditor in CHARGED_TO.may_load(storage, (expense.id, &share.member))?.unwrap_or_default() {
+                let key = ((expense.group_id, &share.member), &creditor, expense.id);
+                UNPAID_CHARGES.remove(storage, key);
+                PAID_CHARGES.remove(storage, key);
+                edges.push((share.member.clone(), creditor));
+            }
+            CHARGED_TO.remove(storage, (expense.id, &share.member));
+        }
+        settle_edges(storage, expense.group_id, &edges)?;
+        refresh_settled(storage, expense.id)
+    }
+
+    // Records that `debtor` owes `creditor` `amount` more. Any debt in the opposite
+    // direction is paid down first, so at most one edge per pair is ever stored.
+    fn add_debt(
+        storage: &mut dyn Storage,
+        group_id: u64,
+        debtor: &Addr,
+        creditor: &Addr,
+        amount: Uint128,
+    ) -> StdResult<()> {
+        let reverse_key = (group_id, creditor, debtor);
+        let reverse_debt = DEBTS.may_load(storage, reverse_key)?.unwrap_or(Uint128::zero());
+        if reverse_debt > amount {
+            return DEBTS.save(storage, reverse_key, &(reverse_debt - amount));
+        }
+        if !reverse_debt.is_zero() {
+            DEBTS.remove(storage, reverse_key);
+        }
+        
+        let remaining = amount - reverse_debt;
+        if remaining.is_zero() {
+            return Ok(());
+        }
+        let debt_key = (group_id, debtor, creditor);
+        let current_debt = DEBTS.may_load(storage, debt_key)?.unwrap_or(Uint128::zero());
+        DEBTS.save(storage, debt_key, &(current_debt + remaining))?;
+        keep_participants(storage, group_id, [debtor, creditor])
+    }
+
+    // Brings anyone a new debt involves who already left back as a departed member,
+    // so the debt can still be settled and is released like any other
+    fn keep_participants(storage: &mut dyn Storage, group_id: u64, users: [&Addr; 2]) -> StdResult<()> {
+        let mut group = GROUPS.load(storage, group_id)?;
+        let returning: Vec<&Addr> = users.into_iter().filter(|user| !is_participant(&group, user)).collect();
+        if returning.is_empty() {
+            return Ok(());
+        }
+        
+        for user in returning {
+            group.departed_members.push(user.clone());
+            let mut user_groups = USER_GROUPS.may_load(storage, user)?.unwrap_or_default();
+            if !user_groups.contains(&group_id) {
+                user_groups.push(group_id);
+                USER_GROUPS.save(storage, user, &user_groups)?;
+            }
+        }
+        GROUPS.save(storage, group_id, &group)
+    }
+
+    // Reduces what `debtor` owes `creditor`. Anything beyond the outstanding debt
+    // (e.g. the share was already paid) becomes a debt in the opposite direction.
+    fn reduce_debt(
+        storage: &mut dyn Storage,
+        group_id: u64,
+        debtor: &Addr,
+        creditor: &Addr,
+        amount: Uint128,
+    ) -> StdResult<()> {
+        let debt_key = (group_id, debtor, creditor);
+        let current_debt = DEBTS.may_load(storage, debt_key)?.unwrap_or(Uint128::zero());
+        if current_debt > amount {
+            return DEBTS.save(storage, debt_key, &(current_debt - amount));
+        }
+        
+        DEBTS.remove(storage, debt_key);
+        let overpaid = amount - current_debt;
+        if !overpaid.is_zero() {
+            add_debt(storage, group_id, creditor, debtor, overpaid)?;
+        }
+        Ok(())
+    }
+
+    fn record_revision(
+        storage: &mut dyn Storage,
+        expense: Expense,
+        change: ExpenseChange,
+        changed_by: &Addr,
+        env: &Env,
+    ) -> StdResult<()> {
+        let mut history = EXPENSE_HISTORY.may_load(storage, expense.id)?.unwrap_or_default();
+        let id = expense.id;
+        history.push(ExpenseRevision {
+            expense,
+            change,
+            changed_by: changed_by.clone(),
+            changed_at: env.block.time,
+        });
+        EXPENSE_HISTORY.save(storage, id, &history)
+    }
+
+    // Splits `amount` across `count` members according to `mode`.
+    // Returns one amount per member, in the same order as the members.
+    pub fn compute_shares(
+        amount: Uint128,
+        count: usize,
+        mode: &SplitMode,
+    ) -> Result<Vec<Uint128>, ContractError> {
+        if count == 0 {
+            return Err(ContractError::InvalidSplit { 
+                reason: "Expense must be split between at least one member".to_string() 
+            });
+        }
+        
+        let check_len = |len: usize| -> Result<(), ContractError> {
+            if len != count {
+                return Err(ContractError::InvalidSplit { 
+                    reason: format!("Expected {} split values, got {}", count, len) 
+                });
+            }
+            Ok(())
+        };
+        
+        match mode {
+            SplitMode::Equal {} => {
+                let split_amount = amount.checked_div(Uint128::from(count as u128))
+                    .map_err(StdError::divide_by_zero)?;
+                Ok(vec![split_amount; count])
+            }
+            SplitMode::Exact { amounts } => {
+                check_len(amounts.len())?;
+                let total = amounts.iter().try_fold(Uint128::zero(), |acc, a| acc.checked_add(*a))
+                    .map_err(StdError::overflow)?;
+                if total != amount {
+                    return Err(ContractError::InvalidSplit { 
+                        reason: format!("Exact amounts sum to {} but the expense is {}", total, amount) 
+                    });
+                }
+                Ok(amounts.clone())
+            }
+            SplitMode::Percentage { basis_points } => {
+                check_len(basis_points.len())?;
+                let total: u64 = basis_points.iter().map(|bp| *bp as u64).sum();
+                if total != TOTAL_BASIS_POINTS as u64 {
+                    return Err(ContractError::InvalidSplit { 
+                        reason: format!("Percentages sum to {} basis points, expected {}", total, TOTAL_BASIS_POINTS) 
+                    });
+                }
+                proportional_shares(amount, basis_points)
+            }
+            SplitMode::Shares { weights } => {
+                check_len(weights.len())?;
+                if weights.iter().all(|w| *w == 0) {
+                    return Err(ContractError::InvalidSplit { 
+                        reason: "At least one weight must be greater than zero".to_string() 
+                    });
+                }
+                proportional_shares(amount, weights)
+            }
+        }
+    }
+
+    // Hands out whatever `compute_shares` lost to rounding so the shares add up to `amount`
+    pub fn allocate_remainder(
+        amount: Uint128,
+        shares: &mut [Uint128],
+        members: &[Addr],
+        mode: &SplitMode,
+        payer: &Addr,
+        policy: RemainderPolicy,
+        expense_id: u64,
+    ) {
+        let allocated: Uint128 = shares.iter().sum();
+        let mut remainder = amount.saturating_sub(allocated).u128();
+        
+        // Members who were given no part of the split never pick up the remainder
+        let eligible: Vec<usize> = (0..shares.len())
+            .filter(|index| match mode {
+                SplitMode::Percentage { basis_points } => basis_points[*index] > 0,
+                SplitMode::Shares { weights } => weights[*index] > 0,
+                SplitMode::Equal {} | SplitMode::Exact { .. } => true,
+            })
+            .collect();
+        if remainder == 0 || eligible.is_empty() {
+            return;
+        }
+        
+        if policy == RemainderPolicy::Payer {
+            if let Some(index) = members.iter().position(|member| member == payer) {
+                if eligible.contains(&index) {
+                    shares[index] += Uint128::new(remainder);
+                    return;
+                }
+            }
+        }
+        
+        // Rotate the starting member by expense so the same person doesn't always pay the dust
+        let count = eligible.len();
+        let mut position = (expense_id % count as u64) as usize;
+        while remainder > 0 {
+            shares[eligible[position]] += Uint128::one();
+            remainder -= 1;
+            position = (position + 1) % count;
+        }
+    }
+
+    // Divides `amount` proportionally to `weights`, rounding each portion down
+    fn proportional_shares(amount: Uint128, weights: &[u32]) -> Result<Vec<Uint128>, ContractError> {
+        let total: u128 = weights.iter().map(|w| *w as u128).sum();
+        weights
+            .iter()
+            .map(|w| {
+                amount
+                    .checked_multiply_ratio(*w as u128, total)
+                    .map_err(|e| ContractError::InvalidSplit { reason: e.to_string() })
+            })
+            .collect()
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::InvalidMigration { 
+            reason: format!("Cannot migrate from {}", stored.contract) 
+        });
+    }
+    
+    let stored_version = migration::parse_version(&stored.version)?;
+    let current_version = migration::parse_version(CONTRACT_VERSION)?;
+    if stored_version > current_version {
+        return Err(ContractError::InvalidMigration { 
+            reason: format!("Cannot downgrade from {} to {}", stored.version, CONTRACT_VERSION) 
+        });
+    }
+    
+    // Run every migration newer than the stored version, oldest first
+    let mut response = Response::new()
+        .add_attribute("method", "migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("to_version", CONTRACT_VERSION);
+    if stored_version < (0, 2, 0) {
+        let owner = match msg.owner {
+            Some(owner) => deps.api.addr_validate(&owner)?,
+            None => return Err(ContractError::InvalidMigration { 
+                reason: "owner is required when migrating from 0.1".to_string() 
+            }),
+        };
+        let netted_pairs = migration::v0_2_0(deps.storage, owner)?;
+        response = response.add_attribute("netted_pairs", netted_pairs.to_string());
+    }
+    if stored_version < (0, 3, 0) {
+        let removed_orphans = migration::v0_3_0(deps.storage)?;
+        response = response.add_attribute("removed_orphans", removed_orphans.to_string());
+    }
+    if stored_version < (0, 4, 0) {
+        let upgraded_expenses = migration::v0_4_0(deps.storage)?;
+        response = response.add_attribute("upgraded_expenses", upgraded_expenses.to_string());
+        let dropped_join_codes = migration::drop_join_codes(deps.storage)?;
+        response = response.add_attribute("dropped_join_codes", dropped_join_codes.to_string());
+    }
+    
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    
+    Ok(response)
+}
+
+pub mod migration {
+    use super::*;
+
+    // Parses a `major.minor.patch` version string
+    pub fn parse_version(version: &str) -> Result<(u64, u64, u64), ContractError> {
+        let invalid = || ContractError::InvalidMigration { 
+            reason: format!("Invalid version {}", version) 
+        };
+        let mut parts = version.split('-').next().unwrap_or_default().split('.');
+        let mut next = || -> Result<u64, ContractError> {
+            parts.next().and_then(|part| part.parse().ok()).ok_or_else(invalid)
+        };
+        Ok((next()?, next()?, next()?))
+    }
+
+    // State as written by 0.1.x
+    pub mod v0_1 {
+        use super::*;
+        use cw_storage_plus::Map;
+        use schemars::JsonSchema;
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+        pub struct Group {
+            pub id: u64,
+            pub name: String,
+            pub creator: Addr,
+            pub members: Vec<Addr>,
+            pub created_at: Timestamp,
+        }
+
+        #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+        pub struct Expense {
+            pub id: u64,
+            pub group_id: u64,
+            pub description: String,
+            pub amount: Uint128,
+            pub paid_by: Addr,
+            pub split_between: Vec<Addr>,
+            pub timestamp: Timestamp,
+            pub settled: bool,
+        }
+
+        pub const GROUPS: Map<u64, Group> = Map::new("groups");
+        pub const EXPENSES: Map<u64, Expense> = Map::new("expenses");
+    }
+
+    // 0.1 -> 0.2: adds the contract config, the settlement and split fields on
+    // groups and expenses, and nets debts that were stored in both directions.
+    // Returns how many debt pairs were netted.
+    pub fn v0_2_0(storage: &mut dyn Storage, owner: Addr) -> Result<u64, ContractError> {
+        CONFIG.save(storage, &Config {
+            owner,
+            default_denom: DEFAULT_DENOM.to_string(),
+            max_group_members: MAX_GROUP_MEMBERS,
+            max_group_name_length: MAX_GROUP_NAME_LENGTH,
+            max_expense_description_length: MAX_EXPENSE_DESCRIPTION_LENGTH,
+        })?;
+        
+        let groups: Vec<(u64, v0_1::Group)> = v0_1::GROUPS
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        for (id, group) in groups {
+            GROUPS.save(storage, id, &Group {
+                id: group.id,
+                name: group.name,
+                creator: group.creator,
+                members: group.members,
+                created_at: group.created_at,
+                remainder_policy: RemainderPolicy::RoundRobin,
+                denom: DEFAULT_DENOM.to_string(),
+                cw20_token: None,
+                privacy: GroupPrivacy::Open,
+                departed_members: vec![],
+                status: GroupStatus::Active,
+                description: None,
+                currency_label: None,
+                avatar_uri: None,
+                tags: BTreeMap::new(),
+                categories: vec![],
+                approval_policy: ApprovalPolicy::None,
+            })?;
+        }
+        
+        let expenses: Vec<(u64, v0_1::Expense)> = v0_1::EXPENSES
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        for (id, expense) in expenses {
+            // 0.1 split equally and dropped the remainder; keep the shares that were
+            // actually written to the debts so edits and deletes reverse them exactly
+            let count = expense.split_between.len() as u128;
+            let split_amount = if count == 0 { 
+                Uint128::zero() 
+            } else { 
+                expense.amount / Uint128::from(count) 
+            };
+            let shares = expense.split_between
+                .iter()
+                .map(|member| Share { member: member.clone(), amount: split_amount, settled: false })
+                .collect();
+            EXPENSES.save(storage, id, &Expense {
+                id: expense.id,
+                group_id: expense.group_id,
+                description: expense.description,
+                amount: expense.amount,
+                payers: vec![Contribution { payer: expense.paid_by.clone(), amount: expense.amount }],
+                paid_by: expense.paid_by,
+                split_between: expense.split_between,
+                split_mode: SplitMode::Equal {},
+                shares,
+                timestamp: expense.timestamp,
+                settled: expense.settled,
+                category: None,
+                tags: vec![],
+                occurred_at: expense.timestamp,
+                status: ExpenseStatus::Active,
+                recorded_by: None,
+                confirmations: vec![],
+                approvals: vec![],
+                disputes: vec![],
+            })?;
+        }
+        
+        Ok(net_debts(storage)?)
+    }
+
+    // 0.2 -> 0.3: groups are now closed instead of deleted when the last member
+    // leaves. Groups deleted by earlier versions left their expenses, history and
+    // debts behind; remove them. Returns how many records were removed.
+    pub fn v0_3_0(storage: &mut dyn Storage) -> StdResult<u64> {
+        let orphaned_expenses: Vec<u64> = EXPENSES
+            .range(storage, None, None, Order::Ascending)
+            .filter_map(|item| match item {
+                Ok((id, expense)) if !GROUPS.has(storage, expense.group_id) => Some(Ok(id)),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .collect::<StdResult<_>>()?;
+        for id in &orphaned_expenses {
+            EXPENSES.remove(storage, *id);
+            EXPENSE_HISTORY.remove(storage, *id);
+        }
+        
+        let orphaned_debts: Vec<(u64, Addr, Addr)> = DEBTS
+            .keys(storage, None, None, Order::Ascending)
+            .filter(|key| key.as_ref().map_or(true, |(group_id, _, _)| !GROUPS.has(storage, *group_id)))
+            .collect::<StdResult<_>>()?;
+        for (group_id, debtor, creditor) in &orphaned_debts {
+            DEBTS.remove(storage, (*group_id, debtor, creditor));
+        }
+        
+        Ok((orphaned_expenses.len() + orphaned_debts.len()) as u64)
+    }
+
+    // 0.3 -> 0.4: expenses now carry the date they happened on and can have
+    // several payers. Older expenses, and the revisions kept of them, are dated
+    // at the time they were recorded and paid in full by paid_by, and every
+    // expense is added to the date indexes. Returns how many expenses were updated.
+    pub fn v0_4_0(storage: &mut dyn Storage) -> StdResult<u64> {
+        let expenses: Vec<(u64, Expense)> = EXPENSES
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        let mut updated = 0;
+        for (id, mut expense) in expenses {
+            if upgrade_expense(&mut expense) {
+                EXPENSES.save(storage, id, &expense)?;
+                updated += 1;
+            }
+            super::execute::index_expense(storage, &expense)?;
+        }
+        
+        let histories: Vec<(u64, Vec<ExpenseRevision>)> = EXPENSE_HISTORY
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        for (id, mut revisions) in histories {
+            for revision in &mut revisions {
+                upgrade_expense(&mut revision.expense);
+            }
+            EXPENSE_HISTORY.save(storage, id, &revisions)?;
+        }
+        
+        // Record what each active expense charged, then hand past settlements to those charges
+        let groups: Vec<(u64, Vec<u64>)> = GROUP_EXPENSES
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        for (group_id, expense_ids) in groups {
+            let mut edges = Vec::new();
+            for id in &expense_ids {
+                let expense = EXPENSES.load(storage, *id)?;
+                if expense.status != ExpenseStatus::Active {
+                    continue;
+                }
+                for (debtor, creditor, amount) in super::execute::expense_debts(&expense)? {
+                    super::execute::record_charge(storage, group_id, expense.id, &debtor, &creditor, amount)?;
+                    edges.push((debtor, creditor));
+                }
+            }
+            super::execute::settle_edges(storage, group_id, &edges)?;
+            for id in expense_ids {
+                super::execute::refresh_settled(storage, id)?;
+            }
+        }
+        
+        Ok(updated)
+    }
+
+    // Join codes before 0.4 were stored by the hash of a shared secret, which can't be
+    // turned into a public key, so they are removed and have to be minted again.
+    // Returns how many codes were dropped.
+    pub fn drop_join_codes(storage: &mut dyn Storage) -> StdResult<u64> {
+        let groups: Vec<(u64, Vec<String>)> = GROUP_JOIN_CODES
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        let mut dropped = 0;
+        for (group_id, code_hashes) in groups {
+            for code_hash in code_hashes {
+                JOIN_CODES.remove(storage, &code_hash);
+                dropped += 1;
+            }
+            GROUP_JOIN_CODES.remove(storage, group_id);
+        }
+        Ok(dropped)
+    }
+
+    // Fills in the fields 0.4 added to an expense. Returns whether anything changed.
+    fn upgrade_expense(expense: &mut Expense) -> bool {
+        let mut changed = false;
+        if expense.occurred_at == Timestamp::default() {
+            expense.occurred_at = expense.timestamp;
+            changed = true;
+        }
+        if expense.payers.is_empty() {
+            expense.payers = vec![Contribution { payer: expense.paid_by.clone(), amount: expense.amount }];
+            changed = true;
+        }
+        changed
+    }
+
+    // Collapses every pair of opposite debts into a single edge. Returns how many pairs were netted.
+    pub fn net_debts(storage: &mut dyn Storage) -> StdResult<u64> {
+        let debts: Vec<((u64, Addr, Addr), Uint128)> = DEBTS
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        
+        let mut netted_pairs = 0;
+        for ((group_id, debtor, creditor), amount) in debts {
+            // Each pair shows up twice; only handle it from the smaller address
+            if debtor > creditor {
+                continue;
+            }
+            let reverse_key = (group_id, &creditor, &debtor);
+            let reverse_amount = match DEBTS.may_load(storage, reverse_key)? {
+                Some(reverse_amount) => reverse_amount,
+                None => continue,
+            };
+            
+            let debt_key = (group_id, &debtor, &creditor);
+            DEBTS.remove(storage, debt_key);
+            DEBTS.remove(storage, reverse_key);
+            if amount > reverse_amount {
+                DEBTS.save(storage, debt_key, &(amount - reverse_amount))?;
+            } else if reverse_amount > amount {
+                DEBTS.save(storage, reverse_key, &(reverse_amount - amount))?;
+            }
+            netted_pairs += 1;
+        }
+        
+        Ok(netted_pairs)
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::GetConfig {} => 
+            to_json_binary(&query::get_config(deps)?),
+        QueryMsg::GetGroup { id } => 
+            to_json_binary(&query::get_group(deps, id)?),
+        QueryMsg::GetGroupRoles { group_id } => 
+            to_json_binary(&query::get_group_roles(deps, group_id)?),
+        QueryMsg::GetPendingOwnership { group_id } => 
+            to_json_binary(&query::get_pending_ownership(deps, group_id)?),
+        QueryMsg::GetUserInvites { user } => 
+            to_json_binary(&query::get_user_invites(deps, user)?),
+        QueryMsg::GetGroupInvites { group_id } => 
+            to_json_binary(&query::get_group_invites(deps, group_id)?),
+        QueryMsg::GetJoinRequests { group_id } => 
+            to_json_binary(&query::get_join_requests(deps, group_id)?),
+        QueryMsg::GetJoinCodes { group_id } => 
+            to_json_binary(&query::get_join_codes(deps, env, group_id)?),
+        QueryMsg::GetUserGroups { user, limit, start_after, include_archived } => 
+            to_json_binary(&query::get_user_groups(deps, user, limit, start_after, include_archived)?),
+        QueryMsg::GetExpense { id } => 
+            to_json_binary(&query::get_expense(deps, id)?),
+        QueryMsg::GetPendingExpenses { user, group_id, limit, start_after } => 
+            to_json_binary(&query::get_pending_expenses(deps, user, group_id, limit, start_after)?),
+        QueryMsg::GetExpenseHistory { id } => 
+            to_json_binary(&query::get_expense_history(deps, id)?),
+        QueryMsg::GetGroupExpenses { group_id, limit, start_after, filter } => 
+            to_json_binary(&query::get_group_expenses(deps, group_id, limit, start_after, filter.unwrap_or_default())?),
+        QueryMsg::GetCategoryTotals { group_id, from, to, date } => 
+            to_json_binary(&query::get_category_totals(deps, group_id, from, to, date.unwrap_or_default())?),
+        QueryMsg::GetDebts { group_id } => 
+            to_json_binary(&query::get_debts(deps, group_id)?),
+        QueryMsg::GetSimplifiedDebts { group_id } => 
+            to_json_binary(&query::get_simplified_debts(deps, group_id)?),
+        QueryMsg::GetBalanceSummary { group_id, user } => 
+            to_json_binary(&query::get_balance_summary(deps, group_id, user)?),
+    }
+}
+
+pub mod query {
+    use super::*;
+    use cw_storage_plus::{Bound, Map};
+
+    // Default and maximum number of items to return in a query
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 30;
+
+    pub fn get_config(deps: Deps) -> StdResult<ConfigResponse> {
+        let config = CONFIG.load(deps.storage)?;
+        Ok(ConfigResponse { config })
+    }
+
+    pub fn get_group(deps: Deps, id: u64) -> StdResult<GroupResponse> {
+        let group = GROUPS.load(deps.storage, id)?;
+        Ok(GroupResponse { group })
+    }
+
+    pub fn get_group_roles(deps: Deps, group_id: u64) -> StdResult<GroupRolesResponse> {
+        let group = GROUPS.load(deps.storage, group_id)?;
+        let roles = group.members
+            .iter()
+            .map(|member| {
+                let role = execute::member_role(deps.storage, &group, member)?.unwrap_or(GroupRole::Member);
+                Ok(MemberRole { member: member.clone(), role })
+            })
+            .collect::<StdResult<_>>()?;
+        Ok(GroupRolesResponse { roles })
+    }
+
+    pub fn get_pending_ownership(deps: Deps, group_id: u64) -> StdResult<PendingOwnershipResponse> {
+        let pending = PENDING_OWNERS.may_load(deps.storage, group_id)?;
+        Ok(PendingOwnershipResponse { pending })
+    }
+
+    pub fn get_user_invites(deps: Deps, user: String) -> StdResult<InvitesResponse> {
+        let user_addr = deps.api.addr_validate(&user)?;
+        let invites = USER_INVITES
+            .may_load(deps.storage, &user_addr)?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|group_id| INVITES.load(deps.storage, (group_id, &user_addr)))
+            .collect::<StdResult<_>>()?;
+        Ok(InvitesResponse { invites })
+    }
+
+    pub fn get_group_invites(deps: Deps, group_id: u64) -> StdResult<InvitesResponse> {
+        let invites = INVITES
+            .prefix(group_id)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(_, invite)| invite))
+            .collect::<StdResult<_>>()?;
+        Ok(InvitesResponse { invites })
+    }
+
+    pub fn get_join_requests(deps: Deps, group_id: u64) -> StdResult<JoinRequestsResponse> {
+        let requests = JOIN_REQUESTS
+            .prefix(group_id)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(_, request)| request))
+            .collect::<StdResult<_>>()?;
+        Ok(JoinRequestsResponse { requests })
+    }
+
+    pub fn get_join_codes(deps: Deps, env: Env, group_id: u64) -> StdResult<JoinCodesResponse> {
+        let mut codes = vec![];
+        for public_key in GROUP_JOIN_CODES.may_load(deps.storage, group_id)?.unwrap_or_default() {
+            let code = JOIN_CODES.load(deps.storage, &public_key)?;
+            // Codes that ran out of uses are removed, expired ones are only hidden
+            if code.expires_at.is_none_or(|expires_at| expires_at > env.block.time) {
+                codes.push(code);
+            }
+        }
+        Ok(JoinCodesResponse { codes })
+    }
+
+    pub fn get_user_groups(
+        deps: Deps,
+        user: String,
+        limit: Option<u32>,
+        start_after: Option<u64>,
+        include_archived: Option<bool>,
+    ) -> StdResult<GroupsResponse> {
+        let user_addr = deps.api.addr_validate(&user)?;
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let include_archived = include_archived.unwrap_or(true);
+
+        // Get the groups this user belongs to
+        let user_group_ids = USER_GROUPS
+            .may_load(deps.storage, &user_addr)?
+            .unwrap_or_default();
+
+        // Filter by start_after if provided
+        let filtered_ids: Vec<u64> = if let Some(start_id) = start_after {
+            user_group_ids
+                .into_iter()
+                .filter(|&id| id > start_id)
+                .collect()
+        } else {
+            user_group_ids
+        };
+
+        // Sort by ID (ascending)
+        let mut sorted_ids = filtered_ids;
+        sorted_ids.sort();
+
+        // Load groups from IDs, skipping archived ones if asked to, up to the limit
+        let mut groups: Vec<Group> = Vec::new();
+        for id in sorted_ids {
+            if groups.len() == limit {
+                break;
+            }
+            let group = GROUPS.load(deps.storage, id)?;
+            if include_archived || group.status != GroupStatus::Archived {
+                groups.push(group);
+            }
+        }
+
+        Ok(GroupsResponse { groups })
+    }
+
+    pub fn get_expense(deps: Deps, id: u64) -> StdResult<ExpenseResponse> {
+        let expense = EXPENSES.load(deps.storage, id)?;
+        let frozen_shares = execute::frozen_shares(&expense);
+        Ok(ExpenseResponse { expense, frozen_shares })
+    }
+
+    pub fn get_pending_expenses(
+        deps: Deps,
+        user: String,
+        group_id: Option<u64>,
+        limit: Option<u32>,
+        start_after: Option<u64>,
+    ) -> StdResult<ExpensesResponse> {
+        let user_addr = deps.api.addr_validate(&user)?;
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let group_ids = match group_id {
+            Some(group_id) => vec![group_id],
+            None => USER_GROUPS.may_load(deps.storage, &user_addr)?.unwrap_or_default(),
+        };
+        
+        // Expenses the user paid and has to confirm, is charged by and has yet to approve,
+        // or, for group admins, has open disputes to resolve
+        let mut expenses = Vec::new();
+        for group_id in group_ids {
+            let Some(group) = GROUPS.may_load(deps.storage, group_id)? else {
+                continue;
+            };
+            let is_admin = matches!(
+                execute::member_role(deps.storage, &group, &user_addr)?,
+                Some(GroupRole::Owner | GroupRole::Admin)
+            );
+            
+            // Only expenses waiting on someone need a look; beyond `limit` of them
+            // from one group, none can make it onto the page
+            let mut found = 0;
+            let open_ids = OPEN_EXPENSES
+                .prefix(group_id)
+                .keys(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending);
+            for id in open_ids {
+                if found == limit {
+                    break;
+                }
+                let expense = EXPENSES.load(deps.storage, id?)?;
+                let disputed = expense.disputes.iter().any(|dispute| dispute.outcome.is_none());
+                let awaiting = match expense.status {
+                    ExpenseStatus::PendingConfirmation => execute::unconfirmed_payers(&expense).contains(&user_addr),
+                    ExpenseStatus::PendingApproval => 
+                        execute::approvers(&expense).contains(&user_addr) && !expense.approvals.contains(&user_addr),
+                    _ => false,
+                } || (is_admin && disputed);
+                if awaiting {
+                    expenses.push(expense);
+                    found += 1;
+                }
+            }
+        }
+        expenses.sort_by_key(|expense| expense.id);
+        expenses.truncate(limit);
+        
+        Ok(ExpensesResponse { expenses })
+    }
+
+    pub fn get_expense_history(deps: Deps, id: u64) -> StdResult<ExpenseHistoryResponse> {
+        let revisions = EXPENSE_HISTORY.may_load(deps.storage, id)?.unwrap_or_default();
+        Ok(ExpenseHistoryResponse { revisions })
+    }
+
+    // The date of an expense a query sorts and filters by
+    fn expense_date(expense: &Expense, date: ExpenseDate) -> Timestamp {
+        match date {
+            ExpenseDate::Recorded => expense.timestamp,
+            ExpenseDate::Occurred => expense.occurred_at,
+        }
+    }
+
+    // The index that keeps a group's expenses in order of `date`
+    fn expenses_by_date(date: ExpenseDate) -> Map<'static, (u64, u64, u64), Empty> {
+        match date {
+            ExpenseDate::Recorded => EXPENSES_BY_RECORDED,
+            ExpenseDate::Occurred => EXPENSES_BY_OCCURRED,
+        }
+    }
+
+    // A bound on (time in nanoseconds, expense_id) within a group's date index
+    type DateBound = Option<Bound<'static, (u64, u64)>>;
+
+    // Index bounds for the dates in [from, to)
+    fn date_bounds(from: Option<Timestamp>, to: Option<Timestamp>) -> (DateBound, DateBound) {
+        (
+            from.map(|from| Bound::inclusive((from.nanos(), 0))),
+            to.map(|to| Bound::exclusive((to.nanos(), 0))),
+        )
+    }
+
+    pub fn get_group_expenses(
+        deps: Deps,
+        group_id: u64,
+        limit: Option<u32>,
+        start_after: Option<u64>,
+        filter: ExpenseFilter,
+    ) -> StdResult<ExpensesResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let date = filter.date.unwrap_or_default();
+
+        // Check if the group exists
+        if GROUPS.may_load(deps.storage, group_id)?.is_none() {
+            return Err(StdError::not_found("Group"));
+        }
+
+        // Continue after the given expense, unless the range starts later. If it was
+        // deleted since, its last revision still records where it stood.
+        let (mut min, max) = date_bounds(filter.from, filter.to);
+        if let Some(start_id) = start_after {
+            let start = match EXPENSES.may_load(deps.storage, start_id)? {
+                Some(expense) => expense,
+                None => EXPENSE_HISTORY
+                    .may_load(deps.storage, start_id)?
+                    .and_then(|mut revisions| revisions.pop())
+                    .map(|revision| revision.expense)
+                    .ok_or_else(|| StdError::not_found("Expense"))?,
+            };
+            let cursor = expense_date(&start, date);
+            if filter.from.is_none_or(|from| cursor >= from) {
+                min = Some(Bound::exclusive((cursor.nanos(), start.id)));
+            }
+        }
+
+        // Walk the group's expenses by date, oldest first, with the ID breaking ties
+        let mut expenses = Vec::new();
+        for key in expenses_by_date(date).sub_prefix(group_id).keys(deps.storage, min, max, Order::Ascending) {
+            if expenses.len() == limit {
+                break;
+            }
+            let (_, id) = key?;
+            let expense = EXPENSES.load(deps.storage, id)?;
+            if filter.status.is_none_or(|status| expense.status == status) 
+                && filter.settled.is_none_or(|settled| expense.settled == settled) 
+            {
+                expenses.push(expense);
+            }
+        }
+
+        Ok(ExpensesResponse { expenses })
+    }
+
+    pub fn get_category_totals(
+        deps: Deps,
+        group_id: u64,
+        from: Option<Timestamp>,
+        to: Option<Timestamp>,
+        date: ExpenseDate,
+    ) -> StdResult<CategoryTotalsResponse> {
+        // Check if the group exists
+        GROUPS.may_load(deps.storage, group_id)?.ok_or_else(|| {
+            StdError::not_found("Group")
+        })?;
+        
+        let mut totals: BTreeMap<Option<String>, CategoryTotal> = BTreeMap::new();
+        let (min, max) = date_bounds(from, to);
+        for key in expenses_by_date(date).sub_prefix(group_id).keys(deps.storage, min, max, Order::Ascending) {
+            let (_, id) = key?;
+            let expense = EXPENSES.load(deps.storage, id)?;
+            // Only count what the group has actually agreed to spend
+            if expense.status != ExpenseStatus::Active {
+                continue;
+            }
+            
+            let total = totals.entry(expense.category.clone()).or_insert_with(|| CategoryTotal {
+                category: expense.category,
+                amount: Uint128::zero(),
+                expenses: 0,
+            });
+            total.amount = total.amount.checked_add(expense.amount)?;
+            total.expenses += 1;
+        }
+        
+        Ok(CategoryTotalsResponse { totals: totals.into_values().collect() })
+    }
+
+    pub fn get_debts(deps: Deps, group_id: u64) -> StdResult<DebtsResponse> {
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?.ok_or_else(|| {
+            StdError::not_found("Group")
+        })?;
+
+        let mut debts = Vec::new();
+
+        // Go through all possible pairs of members to find debts
+        for debtor in execute::participants(&group) {
+            for creditor in execute::participants(&group) {
+                if debtor == creditor {
+                    continue;
+                }
+
+                let debt_key = (group_id, debtor, creditor);
+                if let Some(amount) = DEBTS.may_load(deps.storage, debt_key)? {
+                    if !amount.is_zero() {
+                        debts.push(Debt {
+                            debtor: debtor.clone(),
+                            creditor: creditor.clone(),
+                            amount,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(DebtsResponse { debts })
+    }
+
+    pub fn get_simplified_debts(deps: Deps, group_id: u64) -> StdResult<SimplifiedDebtsResponse> {
+        // Check if the group exists
+        if GROUPS.may_load(deps.storage, group_id)?.is_none() {
+            return Err(StdError::not_found("Group"));
+        }
+
+        let transfers = simplified_debts(deps.storage, group_id)?;
+        let opted_in = SIMPLIFY_OPT_INS.may_load(deps.storage, group_id)?.unwrap_or_default();
+
+        Ok(SimplifiedDebtsResponse { transfers, opted_in })
+    }
+
+    // Computes a set of transfers that leaves every member of the group at the same
+    // net position as the stored debts, greedily pairing the largest debtor with the
+    // largest creditor. This needs at most one transfer fewer than there are members
+    // with a non-zero position.
+    pub fn simplified_debts(storage: &dyn Storage, group_id: u64) -> StdResult<Vec<Debt>> {
+        // Net position per member: positive is owed money, negative owes money
+        let mut positions: Vec<(Addr, i128)> = Vec::new();
+        let mut adjust = |member: Addr, delta: i128| {
+            match positions.iter_mut().find(|(addr, _)| *addr == member) {
+                Some((_, position)) => *position += delta,
+                None => positions.push((member, delta)),
+            }
+        };
+        for item in DEBTS.sub_prefix(group_id).range(storage, None, None, Order::Ascending) {
+            let ((debtor, creditor), amount) = item?;
+            let amount = i128::try_from(amount.u128())
+                .map_err(|_| StdError::generic_err("Debt too large to simplify"))?;
+            adjust(debtor, -amount);
+            adjust(creditor, amount);
+        }
+
+        let by_size = |list: &mut Vec<(Addr, u128)>| {
+            list.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        };
+        let mut creditors: Vec<(Addr, u128)> = positions.iter()
+            .filter(|(_, position)| *position > 0)
+            .map(|(addr, position)| (addr.clone(), position.unsigned_abs()))
+            .collect();
+        let mut debtors: Vec<(Addr, u128)> = positions.iter()
+            .filter(|(_, position)| *position < 0)
+            .map(|(addr, position)| (addr.clone(), position.unsigned_abs()))
+            .collect();
+
+        let mut transfers = Vec::new();
+        while !creditors.is_empty() && !debtors.is_empty() {
+            by_size(&mut creditors);
+            by_size(&mut debtors);
+
+            let amount = creditors[0].1.min(debtors[0].1);
+            transfers.push(Debt {
+                debtor: debtors[0].0.clone(),
+                creditor: creditors[0].0.clone(),
+                amount: Uint128::new(amount),
+            });
+
+            creditors[0].1 -= amount;
+            debtors[0].1 -= amount;
+            creditors.retain(|(_, remaining)| *remaining > 0);
+            debtors.retain(|(_, remaining)| *remaining > 0);
+        }
+
+        Ok(transfers)
+    }
+
+    pub fn get_balance_summary(
+        deps: Deps,
+        group_id: u64,
+        user: String,
+    ) -> StdResult<BalanceSummaryResponse> {
+        let user_addr = deps.api.addr_validate(&user)?;
+
+        // Check if the group exists
+        let group = GROUPS.may_load(deps.storage, group_id)?.ok_or_else(|| {
+            StdError::not_found("Group")
+        })?;
+
+        // Check if the user is in the group
+        if !execute::participants(&group).any(|participant| participant == user_addr) {
+            return Err(StdError::generic_err("User is not a member of this group"));
+        }
+
+        let mut balances = Vec::new();
+        let mut total_owed = Uint128::zero();
+        let mut total_owed_to = Uint128::zero();
+
+        // Calculate balances with each other member
+        for other in execute::participants(&group) {
+            if other == user_addr {
+                continue;
+            }
+
+            // Amount user owes to other
+            let debt_key = (group_id, &user_addr, other);
+            let user_owes = DEBTS.may_load(deps.storage, debt_key)?.unwrap_or(Uint128::zero());
+
+            // Amount other owes to user
+            let credit_key = (group_id, other, &user_addr);
+            let other_owes = DEBTS.may_load(deps.storage, credit_key)?.unwrap_or(Uint128::zero());
+
+            // Calculate net balance
+            if !user_owes.is_zero() || !other_owes.is_zero() {
+                if user_owes > other_owes {
+                    total_owed += user_owes - other_owes;
+                } else {
+                    total_owed_to += other_owes - user_owes;
+                }
+
+                // Positive means user is owed, negative means user owes
+                balances.push(Balance {
+                    other_user: other.clone(),
+                    amount: signed(other_owes)? - signed(user_owes)?,
+                });
+            }
+        }
+
+        // Sort balances by amount (largest debts first, largest credits last)
+        balances.sort_by_key(|balance| balance.amount);
+
+        // Calculate net balance
+        let net_balance = signed(total_owed_to)? - signed(total_owed)?;
+
+        Ok(BalanceSummaryResponse {
+            balances,
+            total_owed,
+            total_owed_to,
+            net_balance,
+        })
+    }
+
+    fn signed(amount: Uint128) -> StdResult<Int128> {
+        i128::try_from(amount.u128())
+            .map(Int128::new)
+            .map_err(|_| StdError::generic_err("Balance exceeds the signed range"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, from_json, Addr, Timestamp, Uint128};
+    use k256::ecdsa::signature::hazmat::PrehashSigner;
+    use k256::ecdsa::{Signature, SigningKey};
+
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        let msg = InstantiateMsg::default();
+        let info = mock_info("creator", &coins(1000, "uxion"));
+        
+        let res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+        
+        // Verify group count was initialized to 0
+        let count = GROUP_COUNT.load(deps.as_ref().storage).unwrap();
+        assert_eq!(0u64, count);
+        
+        // Verify expense count was initialized to 0
+        let count = EXPENSE_COUNT.load(deps.as_ref().storage).unwrap();
+        assert_eq!(0u64, count);
+    }
+
+    #[test]
+    fn create_group_works() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(1_000_000);
+        
+        // Initialize the contract
+        let msg = InstantiateMsg::default();
+        let info = mock_info("creator", &coins(1000, "uxion"));
+        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Create a group
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Room 101 Expenses".to_string(),
+            members: vec!["member1".to_string(), "member2".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        
+        // Check attributes
+        assert_eq!(
+            vec![
+                ("action", "create_group"),
+                ("id", "1"),
+                ("creator", "creator"),
+                ("members", "3"),
+            ],
+            res.attributes
+        );
+        
+        // Query the group
+        let query_msg = QueryMsg::GetGroup { id: 1 };
+        let res: GroupResponse = from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        
+        assert_eq!(1, res.group.id);
+        assert_eq!("Room 101 Expenses", res.group.name);
+        assert_eq!(Addr::unchecked("creator"), res.group.creator);
+        assert_eq!(3, res.group.members.len());
+        assert!(res.group.members.contains(&Addr::unchecked("creator")));
+        assert!(res.group.members.contains(&Addr::unchecked("member1")));
+        assert!(res.group.members.contains(&Addr::unchecked("member2")));
+    }
+
+    #[test]
+    fn add_expense_works() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(1_000_000);
+        
+        // Initialize the contract
+        let msg = InstantiateMsg::default();
+        let info = mock_info("creator", &coins(1000, "uxion"));
+        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Create a group
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Room 101 Expenses".to_string(),
+            members: vec!["member1".to_string(), "member2".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Add an expense
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Groceries".to_string(),
+            amount: Uint128::new(150),
+            split_between: vec![],  // Empty means split among all members
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Check attributes
+        assert_eq!(
+            vec![
+                ("action", "add_expense"),
+                ("id", "1"),
+                ("group_id", "1"),
+                ("paid_by", "creator"),
+                ("amount", "150"),
+                ("split_between", "3"),
+            ],
+            res.attributes
+        );
+        
+        // Query the expense
+        let query_msg = QueryMsg::GetExpense { id: 1 };
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        
+        assert_eq!(1, res.expense.id);
+        assert_eq!("Groceries", res.expense.description);
+        assert_eq!(Uint128::new(150), res.expense.amount);
+        assert_eq!(Addr::unchecked("creator"), res.expense.paid_by);
+        assert_eq!(3, res.expense.split_between.len());
+        
+        // Check debts - member1 should owe creator 50 (150/3)
+        let query_msg = QueryMsg::GetDebts { group_id: 1 };
+        let res: DebtsResponse = from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        
+        assert_eq!(2, res.debts.len());  // 2 members owe the creator
+        
+        // Find debt from member1 to creator
+        let debt = res.debts.iter().find(|d| 
+            d.debtor == Addr::unchecked("member1") && 
+            d.creditor == Addr::unchecked("creator")
+        ).unwrap();
+        
+        assert_eq!(Uint128::new(50), debt.amount);  // 150 / 3 = 50
+    }
+
+    #[test]
+    fn settle_debt_works() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(1_000_000);
+        
+        // Initialize the contract
+        let msg = InstantiateMsg::default();
+        let info = mock_info("creator", &coins(1000, "uxion"));
+        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Create a group
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Room 101 Expenses".to_string(),
+            members: vec!["member1".to_string(), "member2".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Add an expense - creator pays
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Groceries".to_string(),
+            amount: Uint128::new(150),
+            split_between: vec![],  // Empty means split among all members
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // member1 settles debt with creator
+        let msg = ExecuteMsg::SettleDebt { 
+            group_id: 1,
+            to: "creator".to_string(),
+            amount: Uint128::new(50),
+        };
+        let info = mock_info("member1", &coins(50, "uxion"));
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Check attributes and message
+        assert_eq!(1, res.messages.len());
+        assert_eq!(
+            vec![
+                ("action", "settle_debt"),
+                ("group_id", "1"),
+                ("from", "member1"),
+                ("to", "creator"),
+                ("amount", "50"),
+                ("remaining_debt", "0"),
+            ],
+            res.attributes
+        );
+        
+        // Check debts - member1 should no longer owe creator
+        let query_msg = QueryMsg::GetDebts { group_id: 1 };
+        let res: DebtsResponse = from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        
+        assert_eq!(1, res.debts.len());  // Only member2 still owes the creator
+        
+        // The remaining debt should be from member2 to creator
+        let debt = &res.debts[0];
+        assert_eq!(Addr::unchecked("member2"), debt.debtor);
+        assert_eq!(Addr::unchecked("creator"), debt.creditor);
+        assert_eq!(Uint128::new(50), debt.amount);
+    }
+
+    #[test]
+    fn settle_all_debts_works() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(1_000_000);
+        
+        // Initialize the contract
+        let msg = InstantiateMsg::default();
+        let info = mock_info("creator", &coins(1000, "uxion"));
+        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Create a group
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Room 101 Expenses".to_string(),
+            members: vec!["member1".to_string(), "member2".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Add expense from creator
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Groceries".to_string(),
+            amount: Uint128::new(150),
+            split_between: vec![],  // Empty means split among all members
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Add expense from member1
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Utilities".to_string(),
+            amount: Uint128::new(90),
+            split_between: vec![],  // Empty means split among all members
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        let info = mock_info("member1", &[]);
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Now member2 owes both creator and member1
+        // member2 settles all debts at once
+        let msg = ExecuteMsg::SettleAllDebts { group_id: 1 };
+        let info = mock_info("member2", &coins(80, "uxion"));  // 50 to creator + 30 to member1
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        // Check messages - should be 2 BankMsg::Send
+        assert_eq!(2, res.messages.len());
+        
+        // Check there are no more debts for member2
+        let query_msg = QueryMsg::GetBalanceSummary { 
+            group_id: 1,
+            user: "member2".to_string()
+        };
+        let res: BalanceSummaryResponse = from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        
+        assert_eq!(Uint128::zero(), res.total_owed);
+        assert_eq!(0, res.balances.len());  // No balances should exist after settling all debts
+    }
+
+    #[test]
+    fn add_expense_with_custom_splits() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        // Initialize the contract and create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Flat".to_string(),
+            members: vec!["member1".to_string(), "member2".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        let split_between = vec!["creator".to_string(), "member1".to_string(), "member2".to_string()];
+        let debt_of = |deps: Deps, debtor: &str| -> Uint128 {
+            DEBTS.may_load(deps.storage, (1, &Addr::unchecked(debtor), &Addr::unchecked("creator")))
+                .unwrap()
+                .unwrap_or_default()
+        };
+        
+        // Rent split by exact amounts
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Rent".to_string(),
+            amount: Uint128::new(1000),
+            split_between: split_between.clone(),
+            split_mode: Some(SplitMode::Exact { 
+                amounts: vec![Uint128::new(500), Uint128::new(300), Uint128::new(200)] 
+            }),
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        assert_eq!(Uint128::new(300), debt_of(deps.as_ref(), "member1"));
+        assert_eq!(Uint128::new(200), debt_of(deps.as_ref(), "member2"));
+        
+        // Dinner split by percentage
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Dinner".to_string(),
+            amount: Uint128::new(200),
+            split_between: split_between.clone(),
+            split_mode: Some(SplitMode::Percentage { basis_points: vec![5000, 2500, 2500] }),
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        assert_eq!(Uint128::new(350), debt_of(deps.as_ref(), "member1"));
+        assert_eq!(Uint128::new(250), debt_of(deps.as_ref(), "member2"));
+        
+        // Utilities split by weights
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Utilities".to_string(),
+            amount: Uint128::new(400),
+            split_between,
+            split_mode: Some(SplitMode::Shares { weights: vec![2, 1, 1] }),
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        assert_eq!(Uint128::new(450), debt_of(deps.as_ref(), "member1"));
+        assert_eq!(Uint128::new(350), debt_of(deps.as_ref(), "member2"));
+        
+        // The resolved shares are stored on the expense
+        let query_msg = QueryMsg::GetExpense { id: 3 };
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        let amounts: Vec<Uint128> = res.expense.shares.iter().map(|s| s.amount).collect();
+        assert_eq!(vec![Uint128::new(200), Uint128::new(100), Uint128::new(100)], amounts);
+        assert_eq!(SplitMode::Shares { weights: vec![2, 1, 1] }, res.expense.split_mode);
+    }
+
+    #[test]
+    fn add_expense_rejects_invalid_splits() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        // Initialize the contract and create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Flat".to_string(),
+            members: vec!["member1".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        
+        let expense = |split_between: Vec<&str>, split_mode: SplitMode| ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Rent".to_string(),
+            amount: Uint128::new(100),
+            split_between: split_between.into_iter().map(String::from).collect(),
+            split_mode: Some(split_mode),
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        
+        let invalid = vec![
+            // Exact amounts must add up to the expense amount
+            expense(vec!["creator", "member1"], SplitMode::Exact { 
+                amounts: vec![Uint128::new(60), Uint128::new(30)] 
+            }),
+            // Percentages must add up to 100%
+            expense(vec!["creator", "member1"], SplitMode::Percentage { basis_points: vec![5000, 4000] }),
+            // One value per member
+            expense(vec!["creator", "member1"], SplitMode::Shares { weights: vec![1] }),
+            // Weights cannot all be zero
+            expense(vec!["creator", "member1"], SplitMode::Shares { weights: vec![0, 0] }),
+            // Members must be listed explicitly
+            expense(vec![], SplitMode::Shares { weights: vec![1, 1] }),
+            // Duplicates would misalign the values
+            expense(vec!["member1", "member1"], SplitMode::Shares { weights: vec![1, 1] }),
+        ];
+        
+        for msg in invalid {
+            let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+            assert!(matches!(err, ContractError::InvalidSplit { .. }), "unexpected error: {}", err);
+        }
+    }
+
+    #[test]
+    fn split_remainder_is_never_dropped() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        // Initialize the contract and create one group per remainder policy
+        instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), InstantiateMsg::default()).unwrap();
+        let info = mock_info("creator", &[]);
+        for policy in [RemainderPolicy::Payer, RemainderPolicy::RoundRobin] {
+            let msg = ExecuteMsg::CreateGroup { 
+                name: "Trip".to_string(),
+                members: vec!["member1".to_string(), "member2".to_string()],
+                remainder_policy: Some(policy),
+                denom: None,
+            };
+            execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        }
+        
+        let total_debt = |deps: Deps, group_id: u64| -> Uint128 {
+            let query_msg = QueryMsg::GetDebts { group_id };
+            let res: DebtsResponse = from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap();
+            res.debts.iter().map(|d| d.amount).sum()
+        };
+        
+        // 100 split three ways: the payer keeps the extra unit in group 1
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Taxi".to_string(),
+            amount: Uint128::new(100),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetExpense { id: 1 }).unwrap()).unwrap();
+        let amounts: Vec<Uint128> = res.expense.shares.iter().map(|s| s.amount).collect();
+        assert_eq!(vec![Uint128::new(34), Uint128::new(33), Uint128::new(33)], amounts);
+        assert_eq!(Uint128::new(66), total_debt(deps.as_ref(), 1));
+        
+        // Conservation: the shares of every expense add up to its amount, whatever the mode
+        let mut seed: u64 = 42;
+        let mut next = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            seed >> 33
+        };
+        let all = vec!["creator".to_string(), "member1".to_string(), "member2".to_string()];
+        for _ in 0..200 {
+            let amount = Uint128::new(next() as u128 % 1_000_000 + 1);
+            let split_mode = match next() % 3 {
+                0 => SplitMode::Equal {},
+                1 => SplitMode::Percentage { basis_points: vec![3333, 3333, 3334] },
+                _ => SplitMode::Shares { weights: vec![next() as u32 % 7 + 1, next() as u32 % 7, 3] },
+            };
+            for group_id in [1, 2] {
+                let msg = ExecuteMsg::AddExpense { 
+                    group_id,
+                    description: "Random".to_string(),
+                    amount,
+                    split_between: all.clone(),
+                    split_mode: Some(split_mode.clone()),
+                    category: None,
+                    tags: None,
+                    occurred_at: None,
+                    payers: None,
+                    paid_by: None,
+                };
+                let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+                let id: u64 = res.attributes[1].value.parse().unwrap();
+                let expense = EXPENSES.load(deps.as_ref().storage, id).unwrap();
+                let total: Uint128 = expense.shares.iter().map(|s| s.amount).sum();
+                assert_eq!(amount, total);
+            }
+        }
+        
+        // Every unit not paid by the creator for themselves is owed to them
+        for group_id in [1, 2] {
+            let own_share: Uint128 = GROUP_EXPENSES.load(deps.as_ref().storage, group_id).unwrap()
+                .into_iter()
+                .map(|id| EXPENSES.load(deps.as_ref().storage, id).unwrap())
+                .map(|e| e.amount - e.shares[0].amount)
+                .sum();
+            assert_eq!(own_share, total_debt(deps.as_ref(), group_id));
+        }
+        
+        // Members given no weight never pick up the remainder, even when they paid
+        for split_mode in [
+            SplitMode::Shares { weights: vec![0, 1, 1] },
+            SplitMode::Percentage { basis_points: vec![0, 5000, 5000] },
+        ] {
+            for group_id in [1, 2] {
+                let msg = ExecuteMsg::AddExpense { 
+                    group_id,
+                    description: "Snacks".to_string(),
+                    amount: Uint128::new(3),
+                    split_between: all.clone(),
+                    split_mode: Some(split_mode.clone()),
+                    category: None,
+                    tags: None,
+                    occurred_at: None,
+                    payers: None,
+                    paid_by: None,
+                };
+                let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+                let id: u64 = res.attributes[1].value.parse().unwrap();
+                let expense = EXPENSES.load(deps.as_ref().storage, id).unwrap();
+                let amounts: Vec<u128> = expense.shares.iter().map(|s| s.amount.u128()).collect();
+                assert_eq!(0, amounts[0]);
+                assert_eq!(3, amounts.iter().sum::<u128>());
+            }
+        }
+    }
+
+    #[test]
+    fn edit_and_delete_expense_reverse_debts() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        // Initialize the contract and create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Flat".to_string(),
+            members: vec!["member1".to_string(), "member2".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        
+        // member1 pays for groceries, with a typo in the amount
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Groceries".to_string(),
+            amount: Uint128::new(3000),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("member1", &[]), msg).unwrap();
+        
+        let debt = |deps: Deps, debtor: &str, creditor: &str| -> Uint128 {
+            DEBTS.may_load(deps.storage, (1, &Addr::unchecked(debtor), &Addr::unchecked(creditor)))
+                .unwrap()
+                .unwrap_or_default()
+        };
+        assert_eq!(Uint128::new(1000), debt(deps.as_ref(), "member2", "member1"));
+        
+        // Other members can't touch it
+        let msg = ExecuteMsg::EditExpense { 
+            id: 1,
+            description: "Groceries".to_string(),
+            amount: Uint128::new(30),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("member2", &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        
+        // The payer fixes the amount and the debts follow
+        execute(deps.as_mut(), env.clone(), mock_info("member1", &[]), msg).unwrap();
+        assert_eq!(Uint128::new(10), debt(deps.as_ref(), "member2", "member1"));
+        assert_eq!(Uint128::new(10), debt(deps.as_ref(), "creator", "member1"));
+        
+        // member2 pays their share before the expense turns out to be a duplicate
+        let msg = ExecuteMsg::SettleDebt { 
+            group_id: 1,
+            to: "member1".to_string(),
+            amount: Uint128::new(10),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("member2", &coins(10, "uxion")), msg).unwrap();
+        
+        // The group admin deletes it: member1 now owes member2 the payment back
+        let msg = ExecuteMsg::DeleteExpense { id: 1 };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(Uint128::zero(), debt(deps.as_ref(), "creator", "member1"));
+        assert_eq!(Uint128::new(10), debt(deps.as_ref(), "member1", "member2"));
+        
+        assert!(query(deps.as_ref(), env.clone(), QueryMsg::GetExpense { id: 1 }).is_err());
+        let res: ExpensesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroupExpenses { 
+            group_id: 1,
+            limit: None,
+            start_after: None,
+            filter: None,
+        }).unwrap()).unwrap();
+        assert!(res.expenses.is_empty());
+        
+        // Both versions are kept for auditing
+        let res: ExpenseHistoryResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetExpenseHistory { id: 1 }).unwrap()).unwrap();
+        assert_eq!(2, res.revisions.len());
+        assert_eq!(ExpenseChange::Edited, res.revisions[0].change);
+        assert_eq!(Uint128::new(3000), res.revisions[0].expense.amount);
+        assert_eq!(ExpenseChange::Deleted, res.revisions[1].change);
+        assert_eq!(Uint128::new(30), res.revisions[1].expense.amount);
+        assert_eq!(Addr::unchecked("creator"), res.revisions[1].changed_by);
+    }
+
+    #[test]
+    fn opposite_debts_are_netted() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        // Initialize the contract and create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Pair".to_string(),
+            members: vec!["bob".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        // Bob pays 100 (Alice owes 50), then Alice pays 60 (Bob owes 30)
+        for (payer, amount) in [("bob", 100), ("alice", 60)] {
+            let msg = ExecuteMsg::AddExpense { 
+                group_id: 1,
+                description: "Dinner".to_string(),
+                amount: Uint128::new(amount),
+                split_between: vec![],
+                split_mode: None,
+                category: None,
+                tags: None,
+                occurred_at: None,
+                payers: None,
+                paid_by: None,
+            };
+            execute(deps.as_mut(), env.clone(), mock_info(payer, &[]), msg).unwrap();
+        }
+        
+        // Only the net edge remains
+        let res: DebtsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
+        assert_eq!(
+            vec![Debt {
+                debtor: Addr::unchecked("alice"),
+                creditor: Addr::unchecked("bob"),
+                amount: Uint128::new(20),
+            }],
+            res.debts
+        );
+        
+        // Bob can't be asked to pay what was already netted away
+        let msg = ExecuteMsg::SettleDebt { 
+            group_id: 1,
+            to: "alice".to_string(),
+            amount: Uint128::new(30),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(30, "uxion")), msg).unwrap_err();
+        assert!(matches!(err, ContractError::NoDebtExists {}));
+        
+        // Bob pays 60 more (Alice owes 30) which flips the direction
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Taxi".to_string(),
+            amount: Uint128::new(60),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap();
+        let res: DebtsResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
+        assert_eq!(1, res.debts.len());
+        assert_eq!(Addr::unchecked("alice"), res.debts[0].debtor);
+        assert_eq!(Uint128::new(50), res.debts[0].amount);
+    }
+
+    #[test]
+    fn net_debts_collapses_stored_pairs() {
+        let mut deps = mock_dependencies();
+        let alice = Addr::unchecked("alice");
+        let bob = Addr::unchecked("bob");
+        let carol = Addr::unchecked("carol");
+        
+        // Debts written before netting existed can be stored in both directions
+        let storage = &mut deps.storage;
+        DEBTS.save(storage, (1, &alice, &bob), &Uint128::new(50)).unwrap();
+        DEBTS.save(storage, (1, &bob, &alice), &Uint128::new(30)).unwrap();
+        DEBTS.save(storage, (1, &bob, &carol), &Uint128::new(10)).unwrap();
+        DEBTS.save(storage, (1, &carol, &bob), &Uint128::new(10)).unwrap();
+        DEBTS.save(storage, (2, &carol, &alice), &Uint128::new(5)).unwrap();
+        
+        assert_eq!(2, migration::net_debts(storage).unwrap());
+        let debts: Vec<((u64, Addr, Addr), Uint128)> = DEBTS
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                ((1, alice.clone(), bob), Uint128::new(20)),
+                ((2, carol, alice), Uint128::new(5)),
+            ],
+            debts
+        );
+        
+        // Netting again changes nothing
+        assert_eq!(0, migration::net_debts(storage).unwrap());
+    }
+
+    #[test]
+    fn migrate_from_v0_1() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        // State written by 0.1: no config, legacy groups and expenses, debts in both directions
+        let (alice, bob, carol) = (Addr::unchecked("alice"), Addr::unchecked("bob"), Addr::unchecked("carol"));
+        let storage = deps.as_mut().storage;
+        set_contract_version(storage, CONTRACT_NAME, "0.1.0").unwrap();
+        migration::v0_1::GROUPS.save(storage, 1, &migration::v0_1::Group {
+            id: 1,
+            name: "Trip".to_string(),
+            creator: alice.clone(),
+            members: vec![alice.clone(), bob.clone(), carol.clone()],
+            created_at: env.block.time,
+        }).unwrap();
+        migration::v0_1::EXPENSES.save(storage, 1, &migration::v0_1::Expense {
+            id: 1,
+            group_id: 1,
+            description: "Dinner".to_string(),
+            amount: Uint128::new(100),
+            paid_by: alice.clone(),
+            split_between: vec![alice.clone(), bob.clone(), carol.clone()],
+            timestamp: env.block.time,
+            settled: false,
+        }).unwrap();
+        // An expense and a debt left behind by a group that was deleted
+        migration::v0_1::EXPENSES.save(storage, 2, &migration::v0_1::Expense {
+            id: 2,
+            group_id: 2,
+            description: "Taxi".to_string(),
+            amount: Uint128::new(5),
+            paid_by: alice.clone(),
+            split_between: vec![alice.clone(), carol.clone()],
+            timestamp: env.block.time,
+            settled: false,
+        }).unwrap();
+        DEBTS.save(storage, (1, &alice, &bob), &Uint128::new(50)).unwrap();
+        DEBTS.save(storage, (1, &bob, &alice), &Uint128::new(30)).unwrap();
+        DEBTS.save(storage, (1, &bob, &carol), &Uint128::new(10)).unwrap();
+        DEBTS.save(storage, (1, &carol, &bob), &Uint128::new(10)).unwrap();
+        DEBTS.save(storage, (2, &carol, &alice), &Uint128::new(5)).unwrap();
+        
+        // The owner is required because 0.1 had no config
+        let err = migrate(deps.as_mut(), env.clone(), MigrateMsg { owner: None }).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidMigration { .. }));
+        
+        let res = migrate(deps.as_mut(), env.clone(), MigrateMsg { owner: Some("admin".to_string()) }).unwrap();
+        let netted = res.attributes.iter().find(|attr| attr.key == "netted_pairs").unwrap();
+        assert_eq!("2", netted.value);
+        let removed = res.attributes.iter().find(|attr| attr.key == "removed_orphans").unwrap();
+        assert_eq!("2", removed.value);
+        assert!(!EXPENSES.has(deps.as_ref().storage, 2));
+        assert_eq!(CONTRACT_VERSION, get_contract_version(deps.as_ref().storage).unwrap().version);
+        
+        let debts: Vec<((u64, Addr, Addr), Uint128)> = DEBTS
+            .range(deps.as_ref().storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(
+            vec![((1, alice.clone(), bob.clone()), Uint128::new(20))],
+            debts
+        );
+        
+        // Legacy records now load with the new fields filled in
+        let config = CONFIG.load(deps.as_ref().storage).unwrap();
+        assert_eq!(Addr::unchecked("admin"), config.owner);
+        let group = GROUPS.load(deps.as_ref().storage, 1).unwrap();
+        assert_eq!(DEFAULT_DENOM, group.denom);
+        let expense = EXPENSES.load(deps.as_ref().storage, 1).unwrap();
+        assert_eq!(SplitMode::Equal {}, expense.split_mode);
+        assert_eq!(vec![Uint128::new(33); 3], expense.shares.iter().map(|share| share.amount).collect::<Vec<_>>());
+        let res: ExpensesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroupExpenses { 
+            group_id: 1, limit: None, start_after: None, filter: Some(ExpenseFilter { date: Some(ExpenseDate::Occurred), ..ExpenseFilter::default() }),
+        }).unwrap()).unwrap();
+        assert_eq!(vec![1], res.expenses.iter().map(|expense| expense.id).collect::<Vec<_>>());
+        
+        // Running it again is a no-op, but a different contract or a downgrade is refused
+        migrate(deps.as_mut(), env.clone(), MigrateMsg { owner: None }).unwrap();
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "9.0.0").unwrap();
+        let err = migrate(deps.as_mut(), env.clone(), MigrateMsg { owner: None }).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidMigration { .. }));
+        set_contract_version(deps.as_mut().storage, "crates.io:other", "0.1.0").unwrap();
+        let err = migrate(deps.as_mut(), env, MigrateMsg { owner: None }).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidMigration { .. }));
+    }
+
+    #[test]
+    fn migration_drops_hashed_join_codes() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        // A join code as 0.3 stored it, by the hash of its secret
+        let code_hash = hex::encode(Sha256::digest(b"team-secret"));
+        let storage = deps.as_mut().storage;
+        set_contract_version(storage, CONTRACT_NAME, "0.3.0").unwrap();
+        let legacy = format!(
+            r#"{{"group_id":1,"code_hash":"{}","created_by":"alice","created_at":"0","expires_at":null,"max_uses":null,"uses":0}}"#,
+            code_hash,
+        );
+        storage.set(&JOIN_CODES.key(&code_hash), legacy.as_bytes());
+        GROUP_JOIN_CODES.save(storage, 1, &vec![code_hash.clone()]).unwrap();
+        
+        let res = migrate(deps.as_mut(), env.clone(), MigrateMsg { owner: None }).unwrap();
+        assert!(res.attributes.iter().any(|attr| attr.key == "dropped_join_codes" && attr.value == "1"));
+        assert!(!JOIN_CODES.has(deps.as_ref().storage, &code_hash));
+        let res: JoinCodesResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetJoinCodes { group_id: 1 }).unwrap()).unwrap();
+        assert!(res.codes.is_empty());
+    }
+
+    #[test]
+    fn config_limits_and_update() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        let msg = InstantiateMsg {
+            max_group_members: Some(2),
+            default_denom: Some("uusdc".to_string()),
+            ..InstantiateMsg::default()
+        };
+        instantiate(deps.as_mut(), env.clone(), mock_info("admin", &[]), msg).unwrap();
+        let res: ConfigResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetConfig {}).unwrap()).unwrap();
+        assert_eq!(Addr::unchecked("admin"), res.config.owner);
+        assert_eq!(2, res.config.max_group_members);
+        assert_eq!(MAX_GROUP_NAME_LENGTH, res.config.max_group_name_length);
+        
+        // New groups default to the configured denom and respect the member limit
+        let create = |members: Vec<&str>| ExecuteMsg::CreateGroup {
+            name: "Trip".to_string(),
+            members: members.into_iter().map(String::from).collect(),
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), create(vec!["bob"])).unwrap();
+        let res: GroupResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroup { id: 1 }).unwrap()).unwrap();
+        assert_eq!("uusdc", res.group.denom);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), ExecuteMsg::JoinGroup { group_id: 1 }).unwrap_err();
+        assert!(matches!(err, ContractError::CustomError { .. }));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), create(vec!["bob", "carol"])).unwrap_err();
+        assert!(matches!(err, ContractError::CustomError { .. }));
+        
+        // Only the owner can update the config, and limits must stay non-zero
+        let update = ExecuteMsg::UpdateConfig {
+            owner: None,
+            default_denom: None,
+            max_group_members: Some(3),
+            max_group_name_length: None,
+            max_expense_description_length: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), update.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let zero = ExecuteMsg::UpdateConfig {
+            owner: None,
+            default_denom: None,
+            max_group_members: Some(0),
+            max_group_name_length: None,
+            max_expense_description_length: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), zero).unwrap_err();
+        assert!(matches!(err, ContractError::CustomError { .. }));
+        execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), update).unwrap();
+        execute(deps.as_mut(), env, mock_info("carol", &[]), ExecuteMsg::JoinGroup { group_id: 1 }).unwrap();
+    }
+
+    #[test]
+    fn simplify_debts_minimizes_transfers() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        // Initialize the contract and create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Trip".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string(), "dave".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        // A chain of payments: everyone pays for the next person's share only
+        for (payer, debtor) in [("bob", "alice"), ("carol", "bob"), ("dave", "carol")] {
+            let msg = ExecuteMsg::AddExpense { 
+                group_id: 1,
+                description: "Coffee".to_string(),
+                amount: Uint128::new(40),
+                split_between: vec![debtor.to_string()],
+                split_mode: None,
+                category: None,
+                tags: None,
+                occurred_at: None,
+                payers: None,
+                paid_by: None,
+            };
+            execute(deps.as_mut(), env.clone(), mock_info(payer, &[]), msg).unwrap();
+        }
+        
+        // Three edges collapse into a single transfer
+        let res: SimplifiedDebtsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetSimplifiedDebts { group_id: 1 }).unwrap()).unwrap();
+        assert_eq!(
+            vec![Debt {
+                debtor: Addr::unchecked("alice"),
+                creditor: Addr::unchecked("dave"),
+                amount: Uint128::new(40),
+            }],
+            res.transfers
+        );
+        
+        // The stored debts are only rewritten once every member has opted in
+        for member in ["alice", "bob", "carol"] {
+            let msg = ExecuteMsg::SetSimplifyDebtsOptIn { group_id: 1, opt_in: true };
+            execute(deps.as_mut(), env.clone(), mock_info(member, &[]), msg).unwrap();
+        }
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::SimplifyDebts { group_id: 1 }).unwrap_err();
+        assert!(matches!(err, ContractError::SimplificationNotApproved {}));
+        
+        let msg = ExecuteMsg::SetSimplifyDebtsOptIn { group_id: 1, opt_in: true };
+        execute(deps.as_mut(), env.clone(), mock_info("dave", &[]), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::SimplifyDebts { group_id: 1 }).unwrap();
+        
+        let res: DebtsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
+        assert_eq!(1, res.debts.len());
+        assert_eq!(Addr::unchecked("alice"), res.debts[0].debtor);
+        assert_eq!(Addr::unchecked("dave"), res.debts[0].creditor);
+        assert_eq!(Uint128::new(40), res.debts[0].amount);
+        
+        // The opt-ins are used up by the rewrite
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::SimplifyDebts { group_id: 1 }).unwrap_err();
+        assert!(matches!(err, ContractError::SimplificationNotApproved {}));
+        
+        // A member who leaves takes their opt-in with them
+        let msg = ExecuteMsg::SetSimplifyDebtsOptIn { group_id: 1, opt_in: true };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), ExecuteMsg::LeaveGroup { group_id: 1 }).unwrap();
+        let opted_in = SIMPLIFY_OPT_INS.load(deps.as_ref().storage, 1).unwrap();
+        assert!(opted_in.is_empty());
+        
+        // Archived groups can't be simplified
+        let msg = ExecuteMsg::SettleDebt { group_id: 1, to: "dave".to_string(), amount: Uint128::new(40) };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &coins(40, "uxion")), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::ArchiveGroup { group_id: 1 }).unwrap();
+        let err = execute(deps.as_mut(), env, mock_info("alice", &[]), ExecuteMsg::SimplifyDebts { group_id: 1 }).unwrap_err();
+        assert!(matches!(err, ContractError::GroupArchived {}));
+    }
+
+    #[test]
+    fn balance_summary_is_signed() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        // Initialize the contract and create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Room 101 Expenses".to_string(),
+            members: vec!["member1".to_string(), "member2".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        
+        // member1 pays 90 (everyone owes 30), creator pays 30 for member2 only
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Utilities".to_string(),
+            amount: Uint128::new(90),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("member1", &[]), msg).unwrap();
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Snacks".to_string(),
+            amount: Uint128::new(10),
+            split_between: vec!["member2".to_string()],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        
+        let query_msg = QueryMsg::GetBalanceSummary { 
+            group_id: 1,
+            user: "creator".to_string()
+        };
+        let res: BalanceSummaryResponse = from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        
+        assert_eq!(
+            vec![
+                Balance { other_user: Addr::unchecked("member1"), amount: Int128::new(-30) },
+                Balance { other_user: Addr::unchecked("member2"), amount: Int128::new(10) },
+            ],
+            res.balances
+        );
+        assert_eq!(Uint128::new(30), res.total_owed);
+        assert_eq!(Uint128::new(10), res.total_owed_to);
+        assert_eq!(Int128::new(-20), res.net_balance);
+    }
+
+    #[test]
+    fn settlement_uses_group_denom() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let usdc = "ibc/498A0751C798A0D9A389AA3691123DADA57DAA4FE165D5C75894505B876BA6E4";
+        
+        // Initialize the contract
+        instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), InstantiateMsg::default()).unwrap();
+        
+        // Denoms are validated when the group is created
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Offsite".to_string(),
+            members: vec!["member1".to_string()],
+            remainder_policy: None,
+            denom: Some("1usdc".to_string()),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidDenom { .. }));
+        
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Offsite".to_string(),
+            members: vec!["member1".to_string()],
+            remainder_policy: None,
+            denom: Some(usdc.to_string()),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Venue".to_string(),
+            amount: Uint128::new(100),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        
+        // The denom can't change while debts are outstanding
+        let msg = ExecuteMsg::UpdateSettlementDenom { group_id: 1, denom: "uxion".to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::OutstandingDebts {}));
+        
+        // uxion no longer counts towards the payment
+        let msg = ExecuteMsg::SettleDebt { 
+            group_id: 1,
+            to: "creator".to_string(),
+            amount: Uint128::new(50),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("member1", &coins(50, "uxion")), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InsufficientFunds { .. }));
+        
+        let msg = ExecuteMsg::SettleAllDebts { group_id: 1 };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("member1", &coins(50, usdc)), msg).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".to_string(),
+                amount: coins(50, usdc),
+            })],
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>()
+        );
+        
+        // Once settled, only the admin may switch denoms
+        let msg = ExecuteMsg::UpdateSettlementDenom { group_id: 1, denom: "uxion".to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("member1", &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        
+        let res: GroupResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetGroup { id: 1 }).unwrap()).unwrap();
+        assert_eq!("uxion", res.group.denom);
+    }
+
+    #[test]
+    fn settlement_refunds_surplus_and_other_denoms() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        // Initialize the contract and create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Room 101 Expenses".to_string(),
+            members: vec!["member1".to_string(), "member2".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        
+        // Everyone owes the creator 50
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Groceries".to_string(),
+            amount: Uint128::new(150),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        
+        let funds = vec![coin(70, "uxion"), coin(5, "uatom"), coin(3, "ibc/ABC")];
+        
+        // member1 pays 30 with 70 uxion and some stray coins attached
+        let msg = ExecuteMsg::SettleDebt { 
+            group_id: 1,
+            to: "creator".to_string(),
+            amount: Uint128::new(30),
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("member1", &funds), msg).unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "creator".to_string(),
+                    amount: coins(30, "uxion"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "member1".to_string(),
+                    amount: vec![coin(3, "ibc/ABC"), coin(5, "uatom"), coin(40, "uxion")],
+                }),
+            ],
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>()
+        );
+        
+        // member2 settles everything with the same overpayment
+        let msg = ExecuteMsg::SettleAllDebts { group_id: 1 };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("member2", &funds), msg).unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "creator".to_string(),
+                    amount: coins(50, "uxion"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "member2".to_string(),
+                    amount: vec![coin(3, "ibc/ABC"), coin(5, "uatom"), coin(20, "uxion")],
+                }),
+            ],
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>()
+        );
+        
+        // Nothing is sent back when the exact amount is paid
+        let msg = ExecuteMsg::SettleDebt { 
+            group_id: 1,
+            to: "creator".to_string(),
+            amount: Uint128::new(20),
+        };
+        let res = execute(deps.as_mut(), env, mock_info("member1", &coins(20, "uxion")), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+    }
+
+    #[test]
+    fn roles_are_enforced() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Office".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string(), "dave".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let grant = |member: &str, role: GroupRole| ExecuteMsg::GrantRole { 
+            group_id: 1, 
+            member: member.to_string(), 
+            role,
+        };
+        
+        // Only the owner hands out admin, and nobody can become owner
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), grant("carol", GroupRole::Viewer)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), grant("bob", GroupRole::Admin)).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), grant("carol", GroupRole::Admin)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), grant("bob", GroupRole::Owner)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidRole { .. }));
+        
+        // Admins can make someone a viewer
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), grant("dave", GroupRole::Viewer)).unwrap();
+        let res: GroupRolesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroupRoles { group_id: 1 }).unwrap()).unwrap();
+        let roles: Vec<GroupRole> = res.roles.iter().map(|entry| entry.role).collect();
+        assert_eq!(vec![GroupRole::Owner, GroupRole::Admin, GroupRole::Member, GroupRole::Viewer], roles);
+        
+        // Viewers can't add expenses and aren't charged by default or explicitly
+        let expense = |split_between: Vec<&str>| ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Coffee".to_string(),
+            amount: Uint128::new(90),
+            split_between: split_between.into_iter().map(String::from).collect(),
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("dave", &[]), expense(vec![])).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), expense(vec!["carol", "dave"])).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSplit { .. }));
+        let res = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), expense(vec![])).unwrap();
+        let id: u64 = res.attributes[1].value.parse().unwrap();
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetExpense { id }).unwrap()).unwrap();
+        assert_eq!(3, res.expense.split_between.len());
+        assert!(!res.expense.split_between.contains(&Addr::unchecked("dave")));
+        
+        // Admins can remove anyone's expense, viewers can't touch them
+        let err = execute(deps.as_mut(), env.clone(), mock_info("dave", &[]), ExecuteMsg::DeleteExpense { id }).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), ExecuteMsg::DeleteExpense { id }).unwrap();
+        
+        // The owner can't walk away while others remain, and revoking brings back the member role
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::LeaveGroup { group_id: 1 }).unwrap_err();
+        assert!(matches!(err, ContractError::CustomError { .. }));
+        let msg = ExecuteMsg::RevokeRole { group_id: 1, member: "dave".to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap();
+        execute(deps.as_mut(), env, mock_info("dave", &[]), expense(vec![])).unwrap();
+    }
+
+    #[test]
+    fn private_groups_need_an_invite_or_approval() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Team".to_string(),
+            members: vec!["bob".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let privacy = |privacy: GroupPrivacy| ExecuteMsg::UpdateGroupPrivacy { group_id: 1, privacy };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), privacy(GroupPrivacy::InviteOnly)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), privacy(GroupPrivacy::InviteOnly)).unwrap();
+        
+        // Strangers are turned away, invited users get in and their invite is used up
+        let join = ExecuteMsg::JoinGroup { group_id: 1 };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("mallory", &[]), join.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotInvited {}));
+        let invite = |invitee: &str| ExecuteMsg::InviteMember { group_id: 1, invitee: invitee.to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), invite("carol")).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), invite("dave")).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), invite("dave")).unwrap_err();
+        assert!(matches!(err, ContractError::AlreadyInvited {}));
+        let res: InvitesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroupInvites { group_id: 1 }).unwrap()).unwrap();
+        assert_eq!(2, res.invites.len());
+        
+        execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), join.clone()).unwrap();
+        let res: InvitesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetUserInvites { user: "carol".to_string() }).unwrap()).unwrap();
+        assert!(res.invites.is_empty());
+        
+        // Dave declines the invite
+        let msg = ExecuteMsg::RevokeInvite { group_id: 1, invitee: "dave".to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info("dave", &[]), msg).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("dave", &[]), join.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotInvited {}));
+        
+        // With approval required, joining files a request an admin decides on
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), privacy(GroupPrivacy::ApprovalRequired)).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), mock_info("dave", &[]), join.clone()).unwrap();
+        assert_eq!("request_join", res.attributes[0].value);
+        execute(deps.as_mut(), env.clone(), mock_info("mallory", &[]), join.clone()).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("dave", &[]), join).unwrap_err();
+        assert!(matches!(err, ContractError::JoinRequestPending {}));
+        let res: JoinRequestsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetJoinRequests { group_id: 1 }).unwrap()).unwrap();
+        assert_eq!(2, res.requests.len());
+        
+        let msg = ExecuteMsg::ApproveJoinRequest { group_id: 1, requester: "dave".to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let msg = ExecuteMsg::RejectJoinRequest { group_id: 1, requester: "mallory".to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let res: GroupResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroup { id: 1 }).unwrap()).unwrap();
+        let members: Vec<&str> = res.group.members.iter().map(Addr::as_str).collect();
+        assert_eq!(vec!["alice", "bob", "carol", "dave"], members);
+        let res: JoinRequestsResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetJoinRequests { group_id: 1 }).unwrap()).unwrap();
+        assert!(res.requests.is_empty());
+    }
+
+    #[test]
+    fn join_codes_expire_and_run_out() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Team".to_string(),
+            members: vec!["bob".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let msg = ExecuteMsg::UpdateGroupPrivacy { group_id: 1, privacy: GroupPrivacy::InviteOnly };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        // Join codes are key pairs whose secret key is handed to the people invited
+        let team_key = SigningKey::from_bytes(&[1u8; 32].into()).unwrap();
+        let day_key = SigningKey::from_bytes(&[2u8; 32].into()).unwrap();
+        let public_key = |key: &SigningKey| hex::encode(key.verifying_key().to_encoded_point(true).as_bytes());
+        let create = |key: &SigningKey, expires_at: Option<Timestamp>, max_uses: Option<u32>| ExecuteMsg::CreateJoinCode {
+            group_id: 1,
+            public_key: public_key(key),
+            expires_at,
+            max_uses,
+        };
+        
+        // Only admins mint codes, and only well-formed public keys are accepted
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), create(&team_key, None, Some(2))).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let msg = ExecuteMsg::CreateJoinCode { 
+            group_id: 1, 
+            public_key: hex::encode(Sha256::digest(b"team-secret")), 
+            expires_at: None, 
+            max_uses: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidJoinCode { .. }));
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), create(&team_key, None, Some(2))).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), create(&day_key, Some(env.block.time.plus_days(1)), None)).unwrap();
+        
+        // Joiners sign their own address with the code's secret key
+        let sign = |key: &SigningKey, signer: &str| -> String {
+            let signature: Signature = key.sign_prehash(&Sha256::digest(signer.as_bytes())).unwrap();
+            hex::encode(signature.to_bytes())
+        };
+        let join = |key: &SigningKey, signature: String| ExecuteMsg::JoinGroupWithCode { public_key: public_key(key), signature };
+        let wrong_key = SigningKey::from_bytes(&[3u8; 32].into()).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), join(&team_key, sign(&wrong_key, "carol"))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidJoinCode { .. }));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), join(&wrong_key, sign(&wrong_key, "carol"))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidJoinCode { .. }));
+        
+        // A code with two uses lets two people in and then disappears
+        let carol_signature = sign(&team_key, "carol");
+        execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), join(&team_key, carol_signature.clone())).unwrap();
+        let res: JoinCodesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetJoinCodes { group_id: 1 }).unwrap()).unwrap();
+        assert_eq!(1, res.codes[0].uses);
+        
+        // Carol's signature, now public, doesn't let anyone else in
+        let err = execute(deps.as_mut(), env.clone(), mock_info("erin", &[]), join(&team_key, carol_signature)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidJoinCode { .. }));
+        
+        execute(deps.as_mut(), env.clone(), mock_info("dave", &[]), join(&team_key, sign(&team_key, "dave"))).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("erin", &[]), join(&team_key, sign(&team_key, "erin"))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidJoinCode { .. }));
+        
+        // Expired codes stop working and drop out of the listing
+        let mut later = env.clone();
+        later.block.time = env.block.time.plus_days(2);
+        let err = execute(deps.as_mut(), later.clone(), mock_info("erin", &[]), join(&day_key, sign(&day_key, "erin"))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidJoinCode { .. }));
+        let res: JoinCodesResponse = from_json(query(deps.as_ref(), later, QueryMsg::GetJoinCodes { group_id: 1 }).unwrap()).unwrap();
+        assert!(res.codes.is_empty());
+        
+        // Revoked codes are gone straight away
+        let msg = ExecuteMsg::RevokeJoinCode { public_key: public_key(&day_key).to_uppercase() };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("erin", &[]), join(&day_key, sign(&day_key, "erin"))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidJoinCode { .. }));
+        
+        let res: GroupResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetGroup { id: 1 }).unwrap()).unwrap();
+        assert_eq!(4, res.group.members.len());
+    }
+
+    #[test]
+    fn removed_members_keep_debts_until_settled() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Flat".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        // Bob owes Alice 30
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Rent".to_string(),
+            amount: Uint128::new(60),
+            split_between: vec!["alice".to_string(), "bob".to_string()],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let remove = |member: &str| ExecuteMsg::RemoveMember { group_id: 1, member: member.to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), remove("bob")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        
+        // Carol has no debts and is gone for good
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), remove("carol")).unwrap();
+        let res: GroupsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetUserGroups { 
+            user: "carol".to_string(), limit: None, start_after: None, include_archived: None,
+        }).unwrap()).unwrap();
+        assert!(res.groups.is_empty());
+        
+        // Bob departs with the debt still open
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), remove("bob")).unwrap();
+        assert_eq!(("departed", "true"), (res.attributes[4].key.as_str(), res.attributes[4].value.as_str()));
+        let res: GroupResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroup { id: 1 }).unwrap()).unwrap();
+        assert_eq!(vec![Addr::unchecked("alice")], res.group.members);
+        assert_eq!(vec![Addr::unchecked("bob")], res.group.departed_members);
+        let res: DebtsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
+        assert_eq!(Uint128::new(30), res.debts[0].amount);
+        
+        // A departed member can't post expenses but can still pay up
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Snacks".to_string(),
+            amount: Uint128::new(10),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::UserNotInGroup {}));
+        let msg = ExecuteMsg::SettleAllDebts { group_id: 1 };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(30, "uxion")), msg).unwrap();
+        
+        // Once settled, they are removed completely
+        let res: GroupResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroup { id: 1 }).unwrap()).unwrap();
+        assert!(res.group.departed_members.is_empty());
+        let res: GroupsResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetUserGroups { 
+            user: "bob".to_string(), limit: None, start_after: None, include_archived: None,
+        }).unwrap()).unwrap();
+        assert!(res.groups.is_empty());
+    }
+
+    #[test]
+    fn last_member_leaving_closes_group() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Trip".to_string(),
+            members: vec!["bob".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let msg = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Fuel".to_string(),
+            amount: Uint128::new(40),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let msg = ExecuteMsg::SettleDebt { group_id: 1, to: "alice".to_string(), amount: Uint128::new(20) };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(20, "uxion")), msg).unwrap();
+        let msg = ExecuteMsg::InviteMember { group_id: 1, invitee: "carol".to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let leave = ExecuteMsg::LeaveGroup { group_id: 1 };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), leave.clone()).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), leave).unwrap();
+        
+        // The group and its expenses are still there, reported as closed
+        let res: GroupResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroup { id: 1 }).unwrap()).unwrap();
+        assert_eq!(GroupStatus::Closed, res.group.status);
+        assert!(res.group.members.is_empty());
+        let res: ExpensesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroupExpenses { 
+            group_id: 1, limit: None, start_after: None, filter: None,
+        }).unwrap()).unwrap();
+        assert_eq!(1, res.expenses.len());
+        
+        // Pending invites are gone and nobody can get back in
+        let res: InvitesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetUserInvites { user: "carol".to_string() }).unwrap()).unwrap();
+        assert!(res.invites.is_empty());
+        let err = execute(deps.as_mut(), env, mock_info("carol", &[]), ExecuteMsg::JoinGroup { group_id: 1 }).unwrap_err();
+        assert!(matches!(err, ContractError::GroupClosed {}));
+    }
+
+    #[test]
+    fn archive_and_reopen_group() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        for name in ["Trip", "Flat"] {
+            let msg = ExecuteMsg::CreateGroup { 
+                name: name.to_string(),
+                members: vec!["bob".to_string()],
+                remainder_policy: None,
+                denom: None,
+            };
+            execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        }
+        let expense = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Museum".to_string(),
+            amount: Uint128::new(30),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense.clone()).unwrap();
+        
+        // Open debts and non-admins keep the group active
+        let archive = ExecuteMsg::ArchiveGroup { group_id: 1 };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), archive.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::OutstandingDebts {}));
+        let msg = ExecuteMsg::SettleAllDebts { group_id: 1 };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(15, "uxion")), msg).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), archive.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), archive).unwrap();
+        
+        // Archived groups take no new expenses or members but can still be listed
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::GroupArchived {}));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), ExecuteMsg::JoinGroup { group_id: 1 }).unwrap_err();
+        assert!(matches!(err, ContractError::GroupArchived {}));
+        
+        let user_groups = |include_archived: Option<bool>| QueryMsg::GetUserGroups { 
+            user: "bob".to_string(), limit: None, start_after: None, include_archived,
+        };
+        let res: GroupsResponse = from_json(query(deps.as_ref(), env.clone(), user_groups(None)).unwrap()).unwrap();
+        assert_eq!(2, res.groups.len());
+        assert_eq!(GroupStatus::Archived, res.groups[0].status);
+        let res: GroupsResponse = from_json(query(deps.as_ref(), env.clone(), user_groups(Some(false))).unwrap()).unwrap();
+        assert_eq!(vec![2], res.groups.iter().map(|group| group.id).collect::<Vec<_>>());
+        
+        // Reopening brings it back into use
+        let reopen = ExecuteMsg::ReopenGroup { group_id: 1 };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), reopen.clone()).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), reopen).unwrap_err();
+        assert!(matches!(err, ContractError::CustomError { .. }));
+        execute(deps.as_mut(), env, mock_info("alice", &[]), expense).unwrap();
+    }
+
+    #[test]
+    fn ownership_transfer_needs_acceptance() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Club".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let propose = |new_owner: &str, expires_at: Option<Timestamp>| ExecuteMsg::ProposeOwnershipTransfer { 
+            group_id: 1, 
+            new_owner: new_owner.to_string(),
+            expires_at,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), propose("bob", None)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), propose("mallory", None)).unwrap_err();
+        assert!(matches!(err, ContractError::UserNotInGroup {}));
+        
+        // An expired offer can't be accepted
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), propose("bob", Some(env.block.time.plus_seconds(60)))).unwrap();
+        assert_eq!("group_ownership_proposed", res.events[0].ty);
+        let accept = ExecuteMsg::AcceptOwnership { group_id: 1 };
+        let mut later = env.clone();
+        later.block.time = env.block.time.plus_seconds(120);
+        let err = execute(deps.as_mut(), later, mock_info("bob", &[]), accept.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::OwnershipTransferExpired {}));
+        
+        // A new offer replaces the old one, and only its recipient can accept
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), propose("carol", None)).unwrap();
+        let res: PendingOwnershipResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetPendingOwnership { group_id: 1 }).unwrap()).unwrap();
+        assert_eq!(Addr::unchecked("carol"), res.pending.unwrap().new_owner);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), accept.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NoPendingOwnership {}));
+        let res = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), accept).unwrap();
+        assert_eq!("group_ownership_transferred", res.events[0].ty);
+        
+        // Carol owns the group now and Alice stays on as an admin
+        let res: GroupRolesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroupRoles { group_id: 1 }).unwrap()).unwrap();
+        let roles: Vec<GroupRole> = res.roles.iter().map(|entry| entry.role).collect();
+        assert_eq!(vec![GroupRole::Admin, GroupRole::Member, GroupRole::Owner], roles);
+        let res: PendingOwnershipResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetPendingOwnership { group_id: 1 }).unwrap()).unwrap();
+        assert!(res.pending.is_none());
+        
+        // Alice is free to leave
+        execute(deps.as_mut(), env, mock_info("alice", &[]), ExecuteMsg::LeaveGroup { group_id: 1 }).unwrap();
+    }
+
+    #[test]
+    fn update_group_metadata() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Trip".to_string(),
+            members: vec!["bob".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let update = |name: Option<&str>, description: Option<&str>, tags: Option<Vec<(&str, &str)>>| ExecuteMsg::UpdateGroup {
+            group_id: 1,
+            name: name.map(String::from),
+            description: description.map(String::from),
+            currency_label: None,
+            avatar_uri: None,
+            tags: tags.map(|tags| tags.into_iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()),
+            categories: None,
+        };
+        
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), update(Some("Lisbon"), None, None)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), update(Some(" "), None, None)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidGroupName { .. }));
+        let long_value = "x".repeat(MAX_TAG_LENGTH + 1);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), update(None, None, Some(vec![("city", &long_value)]))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidGroupMetadata { .. }));
+        
+        let res = execute(
+            deps.as_mut(), 
+            env.clone(), 
+            mock_info("alice", &[]), 
+            update(Some("Lisbon 2025"), Some("Spring trip"), Some(vec![("city", "Lisbon"), ("season", "spring")])),
+        ).unwrap();
+        assert_eq!("group_updated", res.events[0].ty);
+        assert!(res.events[0].attributes.iter().any(|attr| attr.key == "tag.city" && attr.value == "Lisbon"));
+        
+        // Omitted fields are kept and empty strings clear optional ones
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), update(None, Some(""), None)).unwrap();
+        let res: GroupResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetGroup { id: 1 }).unwrap()).unwrap();
+        assert_eq!("Lisbon 2025", res.group.name);
+        assert_eq!(None, res.group.description);
+        assert_eq!(Some(&"spring".to_string()), res.group.tags.get("season"));
+    }
+
+    #[test]
+    fn category_totals() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Trip".to_string(),
+            members: vec!["bob".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let msg = ExecuteMsg::UpdateGroup {
+            group_id: 1,
+            name: None,
+            description: None,
+            currency_label: None,
+            avatar_uri: None,
+            tags: None,
+            categories: Some(vec!["food".to_string(), "transport".to_string()]),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let expense = |amount: u128, category: Option<&str>, tags: Vec<&str>| ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Expense".to_string(),
+            amount: Uint128::new(amount),
+            split_between: vec![],
+            split_mode: None,
+            category: category.map(String::from),
+            tags: Some(tags.into_iter().map(String::from).collect()),
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(10, Some("hotel"), vec![])).unwrap_err();
+        assert!(matches!(err, ContractError::UnknownCategory { .. }));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(10, None, vec![""])).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidExpenseTags { .. }));
+        
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(30, Some("food"), vec!["dinner", "day-1", "dinner"])).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), expense(12, Some("transport"), vec![])).unwrap();
+        let mut later = env.clone();
+        later.block.time = env.block.time.plus_days(1);
+        execute(deps.as_mut(), later.clone(), mock_info("bob", &[]), expense(20, Some("food"), vec![])).unwrap();
+        execute(deps.as_mut(), later.clone(), mock_info("alice", &[]), expense(8, None, vec![])).unwrap();
+        
+        let res: ExpensesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroupExpenses { 
+            group_id: 1, limit: None, start_after: None, filter: None,
+        }).unwrap()).unwrap();
+        assert_eq!(vec!["day-1".to_string(), "dinner".to_string()], res.expenses[0].tags);
+        
+        let totals = |from: Option<Timestamp>, to: Option<Timestamp>| -> Vec<(Option<String>, u128, u32)> {
+            let res: CategoryTotalsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetCategoryTotals { 
+                group_id: 1, from, to, date: None,
+            }).unwrap()).unwrap();
+            res.totals.into_iter().map(|total| (total.category, total.amount.u128(), total.expenses)).collect()
+        };
+        assert_eq!(
+            vec![(None, 8, 1), (Some("food".to_string()), 50, 2), (Some("transport".to_string()), 12, 1)],
+            totals(None, None)
+        );
+        assert_eq!(
+            vec![(Some("food".to_string()), 30, 1), (Some("transport".to_string()), 12, 1)],
+            totals(None, Some(later.block.time))
+        );
+        assert_eq!(
+            vec![(None, 8, 1), (Some("food".to_string()), 20, 1)],
+            totals(Some(later.block.time), None)
+        );
+        
+        // Categories can only be dropped once no expense uses them
+        let update_categories = |categories: Vec<&str>| ExecuteMsg::UpdateGroup {
+            group_id: 1,
+            name: None,
+            description: None,
+            currency_label: None,
+            avatar_uri: None,
+            tags: None,
+            categories: Some(categories.into_iter().map(String::from).collect()),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), update_categories(vec!["food"])).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidGroupMetadata { .. }));
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), update_categories(vec!["food", "transport", "hotel"])).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), update_categories(vec!["transport", "food"])).unwrap();
+    }
+
+    #[test]
+    fn expense_dates() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Trip".to_string(),
+            members: vec!["bob".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let expense = |description: &str, occurred_at: Option<Timestamp>| ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: description.to_string(),
+            amount: Uint128::new(10),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            payers: None,
+            paid_by: None,
+            occurred_at,
+        };
+        
+        // Dates are bounded around the block time
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense("Too old", Some(env.block.time.minus_days(366)))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidExpenseDate { .. }));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense("Too new", Some(env.block.time.plus_days(2)))).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidExpenseDate { .. }));
+        
+        // Recorded in one order, but happened in another
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense("Hotel", Some(env.block.time.minus_days(3)))).unwrap();
+        let hotel: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        let mut later = env.clone();
+        later.block.time = env.block.time.plus_days(1);
+        let res = execute(deps.as_mut(), later.clone(), mock_info("bob", &[]), expense("Lunch", None)).unwrap();
+        let lunch: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        let res = execute(deps.as_mut(), later.clone(), mock_info("bob", &[]), expense("Taxi", Some(env.block.time.minus_days(5)))).unwrap();
+        let taxi: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetExpense { id: lunch }).unwrap()).unwrap();
+        assert_eq!(later.block.time, res.expense.timestamp);
+        assert_eq!(later.block.time, res.expense.occurred_at);
+        
+        let expenses = |deps: Deps, start_after: Option<u64>, filter: ExpenseFilter| -> Vec<u64> {
+            let res: ExpensesResponse = from_json(query(deps, env.clone(), QueryMsg::GetGroupExpenses { 
+                group_id: 1, limit: Some(2), start_after, filter: Some(filter),
+            }).unwrap()).unwrap();
+            res.expenses.into_iter().map(|expense| expense.id).collect()
+        };
+        let occurred = |from: Option<Timestamp>, to: Option<Timestamp>| ExpenseFilter { 
+            date: Some(ExpenseDate::Occurred), from, to, status: None, settled: None,
+        };
+        assert_eq!(vec![hotel, lunch], expenses(deps.as_ref(), None, ExpenseFilter::default()));
+        assert_eq!(vec![taxi], expenses(deps.as_ref(), Some(lunch), ExpenseFilter::default()));
+        assert_eq!(vec![taxi, hotel], expenses(deps.as_ref(), None, occurred(None, None)));
+        assert_eq!(vec![lunch], expenses(deps.as_ref(), Some(hotel), occurred(None, None)));
+        assert_eq!(vec![hotel], expenses(deps.as_ref(), None, occurred(Some(env.block.time.minus_days(4)), Some(env.block.time))));
+        assert_eq!(vec![lunch, taxi], expenses(deps.as_ref(), None, ExpenseFilter { from: Some(later.block.time), ..ExpenseFilter::default() }));
+        
+        // Editing keeps the date unless a new one is given
+        let edit = |occurred_at: Option<Timestamp>| ExecuteMsg::EditExpense { 
+            id: hotel,
+            description: "Hotel".to_string(),
+            amount: Uint128::new(12),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            payers: None,
+            occurred_at,
+        };
+        execute(deps.as_mut(), later.clone(), mock_info("alice", &[]), edit(None)).unwrap();
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetExpense { id: hotel }).unwrap()).unwrap();
+        assert_eq!(env.block.time.minus_days(3), res.expense.occurred_at);
+        execute(deps.as_mut(), later.clone(), mock_info("alice", &[]), edit(Some(later.block.time.plus_hours(2)))).unwrap();
+        assert_eq!(vec![taxi, lunch], expenses(deps.as_ref(), None, occurred(None, None)));
+        
+        // A deleted expense still works as a page cursor
+        execute(deps.as_mut(), later.clone(), mock_info("bob", &[]), ExecuteMsg::DeleteExpense { id: taxi }).unwrap();
+        assert_eq!(vec![lunch, hotel], expenses(deps.as_ref(), Some(taxi), occurred(None, None)));
+    }
+
+    #[test]
+    fn expense_with_multiple_payers() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Dinner".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let payer = |address: &str, amount: u128| Payer { address: address.to_string(), amount: Uint128::new(amount) };
+        let expense = |amount: u128, payers: Vec<Payer>| ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Dinner".to_string(),
+            amount: Uint128::new(amount),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: Some(payers),
+            paid_by: None,
+        };
+        let debts = |deps: Deps| -> Vec<(String, String, u128)> {
+            let res: DebtsResponse = from_json(query(deps, mock_env(), QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
+            let mut debts: Vec<_> = res.debts.into_iter()
+                .map(|debt| (debt.debtor.to_string(), debt.creditor.to_string(), debt.amount.u128()))
+                .collect();
+            debts.sort();
+            debts
+        };
+        
+        // Payments must add up, include the sender and list each member once
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(90, vec![payer("alice", 60), payer("bob", 20)])).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidPayers { .. }));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(90, vec![payer("bob", 90)])).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidPayers { .. }));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(90, vec![payer("alice", 45), payer("alice", 45)])).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidPayers { .. }));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(90, vec![payer("alice", 60), payer("dave", 30)])).unwrap_err();
+        assert!(matches!(err, ContractError::UserNotInGroup {}));
+        
+        // Alice paid 60 and Bob 30 of a 90 dinner split three ways. Everyone's 30 share is owed
+        // two thirds to Alice and one third to Bob; what Alice and Bob owe each other is netted.
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(90, vec![payer("alice", 60), payer("bob", 30)])).unwrap();
+        let id: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        assert_eq!(
+            vec![
+                ("bob".to_string(), "alice".to_string(), 10),
+                ("carol".to_string(), "alice".to_string(), 20),
+                ("carol".to_string(), "bob".to_string(), 10),
+            ],
+            debts(deps.as_ref())
+        );
+        let res: BalanceSummaryResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetBalanceSummary { 
+            group_id: 1, user: "alice".to_string(),
+        }).unwrap()).unwrap();
+        assert_eq!(Int128::new(30), res.net_balance);
+        
+        // Editing keeps the shared payments, which must still match the amount
+        let edit = |amount: u128, payers: Option<Vec<Payer>>| ExecuteMsg::EditExpense { 
+            id,
+            description: "Dinner".to_string(),
+            amount: Uint128::new(amount),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), edit(120, None)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidPayers { .. }));
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), edit(100, Some(vec![payer("alice", 50), payer("carol", 50)]))).unwrap();
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetExpense { id }).unwrap()).unwrap();
+        assert_eq!(2, res.expense.payers.len());
+        
+        // Every member ends up owed what they paid minus their share, down to the last unit
+        for share in &res.expense.shares {
+            let paid = res.expense.payers.iter()
+                .find(|contribution| contribution.payer == share.member)
+                .map_or(0, |contribution| contribution.amount.u128());
+            let summary: BalanceSummaryResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetBalanceSummary { 
+                group_id: 1, user: share.member.to_string(),
+            }).unwrap()).unwrap();
+            assert_eq!(Int128::new(paid as i128 - share.amount.u128() as i128), summary.net_balance);
+        }
+        
+        // Deleting reverses every edge
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::DeleteExpense { id }).unwrap();
+        assert!(debts(deps.as_ref()).is_empty());
+    }
+
+    #[test]
+    fn expenses_recorded_for_another_payer() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Trip".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let expense = |paid_by: &str| ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Museum tickets".to_string(),
+            amount: Uint128::new(30),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: Some(paid_by.to_string()),
+        };
+        let debts = |deps: Deps| -> Vec<Debt> {
+            let res: DebtsResponse = from_json(query(deps, mock_env(), QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
+            res.debts
+        };
+        let pending = |deps: Deps| -> Vec<u64> {
+            let res: ExpensesResponse = from_json(query(deps, mock_env(), QueryMsg::GetGroupExpenses { 
+                group_id: 1, 
+                limit: None, 
+                start_after: None, 
+                filter: Some(ExpenseFilter { status: Some(ExpenseStatus::PendingConfirmation), ..ExpenseFilter::default() }),
+            }).unwrap()).unwrap();
+            res.expenses.into_iter().map(|expense| expense.id).collect()
+        };
+        
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), expense("dave")).unwrap_err();
+        assert!(matches!(err, ContractError::UserNotInGroup {}));
+        
+        // A member recording what Bob paid leaves the debts alone until Bob confirms
+        let res = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), expense("bob")).unwrap();
+        let id: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        assert!(res.attributes.iter().any(|attr| attr.key == "pending" && attr.value == "true"));
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetExpense { id }).unwrap()).unwrap();
+        assert_eq!(Addr::unchecked("bob"), res.expense.paid_by);
+        assert_eq!(Some(Addr::unchecked("carol")), res.expense.recorded_by);
+        assert_eq!(ExpenseStatus::PendingConfirmation, res.expense.status);
+        assert!(debts(deps.as_ref()).is_empty());
+        assert_eq!(vec![id], pending(deps.as_ref()));
+        
+        // Only Bob can confirm it, and only once
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), ExecuteMsg::ConfirmExpense { id }).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), ExecuteMsg::ConfirmExpense { id }).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), ExecuteMsg::ConfirmExpense { id }).unwrap_err();
+        assert!(matches!(err, ContractError::ExpenseNotPending {}));
+        assert!(pending(deps.as_ref()).is_empty());
+        let debts_after_confirm = debts(deps.as_ref());
+        assert_eq!(2, debts_after_confirm.len());
+        assert!(debts_after_confirm.iter().all(|debt| debt.creditor == Addr::unchecked("bob") && debt.amount == Uint128::new(10)));
+        
+        // Admins record for others directly
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense("carol")).unwrap();
+        assert!(res.attributes.iter().any(|attr| attr.key == "pending" && attr.value == "false"));
+        assert!(pending(deps.as_ref()).is_empty());
+        
+        // A pending expense can be dropped by whoever recorded it without touching the debts
+        let res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), expense("carol")).unwrap();
+        let id: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        let before = debts(deps.as_ref());
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), ExecuteMsg::DeleteExpense { id }).unwrap();
+        assert_eq!(before, debts(deps.as_ref()));
+    }
+
+    #[test]
+    fn expenses_need_approval_under_a_policy() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Flat".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string(), "dave".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        
+        let policy = |policy: ApprovalPolicy| ExecuteMsg::UpdateApprovalPolicy { group_id: 1, policy };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), policy(ApprovalPolicy::AllParticipants)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), policy(ApprovalPolicy::Quorum { basis_points: 0 })).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidApprovalPolicy { .. }));
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), policy(ApprovalPolicy::AllParticipants)).unwrap();
+        
+        let expense = |amount: u128, split_between: Vec<&str>| ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Groceries".to_string(),
+            amount: Uint128::new(amount),
+            split_between: split_between.into_iter().map(String::from).collect(),
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        let debts = |deps: Deps| -> Vec<Debt> {
+            let res: DebtsResponse = from_json(query(deps, mock_env(), QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
+            res.debts
+        };
+        let pending_for = |deps: Deps, user: &str| -> Vec<u64> {
+            let res: ExpensesResponse = from_json(query(deps, mock_env(), QueryMsg::GetPendingExpenses { 
+                user: user.to_string(), group_id: None, limit: None, start_after: None,
+            }).unwrap()).unwrap();
+            res.expenses.into_iter().map(|expense| expense.id).collect()
+        };
+        let status = |deps: Deps, id: u64| -> ExpenseStatus {
+            let res: ExpenseResponse = from_json(query(deps, mock_env(), QueryMsg::GetExpense { id }).unwrap()).unwrap();
+            res.expense.status
+        };
+        
+        // Everyone charged has to approve before any debt appears
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(90, vec!["alice", "bob", "carol"])).unwrap();
+        let id: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        assert_eq!(ExpenseStatus::PendingApproval, status(deps.as_ref(), id));
+        assert!(debts(deps.as_ref()).is_empty());
+        assert_eq!(vec![id], pending_for(deps.as_ref(), "bob"));
+        assert!(pending_for(deps.as_ref(), "alice").is_empty());
+        assert!(pending_for(deps.as_ref(), "dave").is_empty());
+        
+        let err = execute(deps.as_mut(), env.clone(), mock_info("dave", &[]), ExecuteMsg::ApproveExpense { id }).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), ExecuteMsg::ApproveExpense { id }).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), ExecuteMsg::ApproveExpense { id }).unwrap_err();
+        assert!(matches!(err, ContractError::AlreadyApproved {}));
+        assert!(debts(deps.as_ref()).is_empty());
+        assert!(pending_for(deps.as_ref(), "bob").is_empty());
+        
+        execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), ExecuteMsg::ApproveExpense { id }).unwrap();
+        assert_eq!(ExpenseStatus::Active, status(deps.as_ref(), id));
+        assert_eq!(2, debts(deps.as_ref()).len());
+        
+        // With a quorum, half of the charged members (rounded up) is enough. A dispute
+        // stops the approval until the expense is edited.
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), policy(ApprovalPolicy::Quorum { basis_points: 5_000 })).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(40, vec![])).unwrap();
+        let id: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        let dispute = |reason: &str| ExecuteMsg::DisputeExpense { id, reason: reason.to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), dispute(" ")).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidDispute { .. }));
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), dispute("I was away that week")).unwrap();
+        assert_eq!(ExpenseStatus::Disputed, status(deps.as_ref(), id));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), ExecuteMsg::ApproveExpense { id }).unwrap_err();
+        assert!(matches!(err, ContractError::ExpenseNotPending {}));
+        assert!(pending_for(deps.as_ref(), "carol").is_empty());
+        
+        let msg = ExecuteMsg::EditExpense { 
+            id,
+            description: "Groceries".to_string(),
+            amount: Uint128::new(30),
+            split_between: vec!["alice".to_string(), "carol".to_string(), "dave".to_string()],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        assert_eq!(ExpenseStatus::PendingApproval, status(deps.as_ref(), id));
+        assert_eq!(vec![id], pending_for(deps.as_ref(), "dave"));
+        
+        let before = debts(deps.as_ref());
+        execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), ExecuteMsg::ApproveExpense { id }).unwrap();
+        assert_eq!(ExpenseStatus::Active, status(deps.as_ref(), id));
+        assert_ne!(before, debts(deps.as_ref()));
+        assert!(pending_for(deps.as_ref(), "dave").is_empty());
+        
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetExpense { id }).unwrap()).unwrap();
+        assert_eq!(1, res.expense.disputes.len());
+        assert_eq!(Addr::unchecked("bob"), res.expense.disputes[0].member);
+        
+        // The expenses waiting on a member are paged by ID
+        let mut ids = vec![];
+        for _ in 0..3 {
+            let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(30, vec!["alice", "bob"])).unwrap();
+            ids.push(res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse::<u64>().unwrap());
         }
-
-        // Sort balances by direction (negative first, then by amount)
-        balances.sort_by(|a, b| {
-            a.direction.cmp(&b.direction).then(a.amount.cmp(&b.amount))
-        });
-
-        // Calculate net balance
-        let net_balance = if total_owed_to >= total_owed {
-            total_owed_to - total_owed
-        } else {
-            Uint128::zero()
+        let page = |start_after: Option<u64>| -> Vec<u64> {
+            let res: ExpensesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetPendingExpenses { 
+                user: "bob".to_string(), group_id: None, limit: Some(2), start_after,
+            }).unwrap()).unwrap();
+            res.expenses.into_iter().map(|expense| expense.id).collect()
         };
-
-        Ok(BalanceSummaryResponse {
-            balances,
-            total_owed,
-            total_owed_to,
-            net_balance,
-        })
+        assert_eq!(ids[..2].to_vec(), page(None));
+        assert_eq!(ids[2..].to_vec(), page(Some(ids[1])));
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, from_json, Addr, Coin, Timestamp, Uint128};
 
     #[test]
-    fn proper_initialization() {
+    fn disputes_freeze_shares_until_resolved() {
         let mut deps = mock_dependencies();
         let env = mock_env();
         
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "uxion"));
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Flat".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
         
-        let res = instantiate(deps.as_mut(), env, info, msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        let expense = ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: "Cleaner".to_string(),
+            amount: Uint128::new(90),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense.clone()).unwrap();
+        let id: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
         
-        // Verify group count was initialized to 0
-        let count = GROUP_COUNT.load(deps.as_ref().storage).unwrap();
-        assert_eq!(0u64, count);
+        let owed = |deps: Deps, debtor: &str| -> u128 {
+            let res: DebtsResponse = from_json(query(deps, mock_env(), QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
+            res.debts.iter()
+                .filter(|debt| debt.debtor == Addr::unchecked(debtor))
+                .map(|debt| debt.amount.u128())
+                .sum()
+        };
+        let get_expense = |deps: Deps| -> ExpenseResponse {
+            from_json(query(deps, mock_env(), QueryMsg::GetExpense { id }).unwrap()).unwrap()
+        };
+        let dispute = ExecuteMsg::DisputeExpense { id, reason: "I only used half the service".to_string() };
+        let resolve = |resolution: DisputeResolution| ExecuteMsg::ResolveDispute { id, resolution };
         
-        // Verify expense count was initialized to 0
-        let count = EXPENSE_COUNT.load(deps.as_ref().storage).unwrap();
-        assert_eq!(0u64, count);
+        // Disputing freezes only the disputer's share
+        assert_eq!(30, owed(deps.as_ref(), "bob"));
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), dispute.clone()).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), dispute.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidDispute { .. }));
+        assert_eq!(0, owed(deps.as_ref(), "bob"));
+        assert_eq!(30, owed(deps.as_ref(), "carol"));
+        let res = get_expense(deps.as_ref());
+        assert_eq!(vec![Share { member: Addr::unchecked("bob"), amount: Uint128::new(30), settled: false }], res.frozen_shares);
+        
+        // Admins see it waiting for them; other members can't resolve it
+        let res: ExpensesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetPendingExpenses { 
+            user: "alice".to_string(), group_id: Some(1), limit: None, start_after: None,
+        }).unwrap()).unwrap();
+        assert_eq!(1, res.expenses.len());
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), resolve(DisputeResolution::Uphold {})).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        
+        // The disputer can withdraw, which brings the share back
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), resolve(DisputeResolution::Uphold {})).unwrap();
+        assert_eq!(30, owed(deps.as_ref(), "bob"));
+        let res = get_expense(deps.as_ref());
+        assert!(res.frozen_shares.is_empty());
+        assert_eq!(Some(DisputeOutcome::Withdrawn), res.expense.disputes[0].outcome);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), resolve(DisputeResolution::Uphold {})).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidDispute { .. }));
+        
+        // An admin can amend the charge
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), dispute.clone()).unwrap();
+        let amend = DisputeResolution::Amend {
+            amount: Uint128::new(75),
+            split_between: vec!["alice".to_string(), "bob".to_string(), "carol".to_string()],
+            split_mode: Some(SplitMode::Exact { amounts: vec![Uint128::new(30), Uint128::new(15), Uint128::new(30)] }),
+            payers: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), resolve(amend)).unwrap();
+        assert_eq!(15, owed(deps.as_ref(), "bob"));
+        assert_eq!(30, owed(deps.as_ref(), "carol"));
+        let res = get_expense(deps.as_ref());
+        assert_eq!(Uint128::new(75), res.expense.amount);
+        assert_eq!("Cleaner", res.expense.description);
+        assert_eq!(Some(DisputeOutcome::Amended), res.expense.disputes[1].outcome);
+        assert_eq!(Some(Addr::unchecked("alice")), res.expense.disputes[1].resolved_by);
+        
+        // Or cancel it, keeping the disputes in the expense history
+        execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), dispute.clone()).unwrap();
+        assert_eq!(0, owed(deps.as_ref(), "carol"));
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), resolve(DisputeResolution::Cancel {})).unwrap();
+        assert_eq!(0, owed(deps.as_ref(), "bob"));
+        assert_eq!(0, owed(deps.as_ref(), "carol"));
+        let res: ExpenseHistoryResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetExpenseHistory { id }).unwrap()).unwrap();
+        let last = res.revisions.last().unwrap();
+        assert_eq!(ExpenseChange::Deleted, last.change);
+        assert_eq!(3, last.expense.disputes.len());
+        assert_eq!(Some(DisputeOutcome::Cancelled), last.expense.disputes[2].outcome);
     }
 
     #[test]
-    fn create_group_works() {
+    fn settlements_pay_off_oldest_expenses_first() {
         let mut deps = mock_dependencies();
-        let mut env = mock_env();
-        env.block.time = Timestamp::from_seconds(1_000_000);
-        
-        // Initialize the contract
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "uxion"));
-        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let env = mock_env();
         
-        // Create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
         let msg = ExecuteMsg::CreateGroup { 
-            name: "Room 101 Expenses".to_string(),
-            members: vec!["member1".to_string(), "member2".to_string()]
+            name: "Flat".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
         };
-        let info = mock_info("creator", &[]);
-        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
         
-        // Check attributes
-        assert_eq!(
-            vec![
-                ("action", "create_group"),
-                ("id", "1"),
-                ("creator", "creator"),
-                ("members", "3"),
-            ],
-            res.attributes
-        );
+        let expense = |description: &str, amount: u128| ExecuteMsg::AddExpense { 
+            group_id: 1,
+            description: description.to_string(),
+            amount: Uint128::new(amount),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense("Rent", 90)).unwrap();
+        let rent: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense("Power", 60)).unwrap();
+        let power: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
         
-        // Query the group
-        let query_msg = QueryMsg::GetGroup { id: 1 };
-        let res: GroupResponse = from_json(&query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        let settle = |amount: u128| ExecuteMsg::SettleDebt { 
+            group_id: 1,
+            to: "alice".to_string(),
+            amount: Uint128::new(amount),
+        };
+        let get_expense = |deps: Deps, id: u64| -> Expense {
+            let res: ExpenseResponse = from_json(query(deps, mock_env(), QueryMsg::GetExpense { id }).unwrap()).unwrap();
+            res.expense
+        };
+        let settled_share = |expense: &Expense, member: &str| -> bool {
+            expense.shares.iter().find(|share| share.member == Addr::unchecked(member)).unwrap().settled
+        };
+        let expenses = |deps: Deps, settled: bool| -> Vec<u64> {
+            let filter = ExpenseFilter { settled: Some(settled), ..ExpenseFilter::default() };
+            let res: ExpensesResponse = from_json(query(deps, mock_env(), QueryMsg::GetGroupExpenses { 
+                group_id: 1, limit: None, start_after: None, filter: Some(filter),
+            }).unwrap()).unwrap();
+            res.expenses.into_iter().map(|expense| expense.id).collect()
+        };
         
-        assert_eq!(1, res.group.id);
-        assert_eq!("Room 101 Expenses", res.group.name);
-        assert_eq!(Addr::unchecked("creator"), res.group.creator);
-        assert_eq!(3, res.group.members.len());
-        assert!(res.group.members.contains(&Addr::unchecked("creator")));
-        assert!(res.group.members.contains(&Addr::unchecked("member1")));
-        assert!(res.group.members.contains(&Addr::unchecked("member2")));
+        // Only the payer's own share starts out settled
+        let res = get_expense(deps.as_ref(), rent);
+        assert!(settled_share(&res, "alice"));
+        assert!(!settled_share(&res, "bob"));
+        assert!(!res.settled);
+        
+        // A partial payment covers the oldest expense first
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(40, "uxion")), settle(40)).unwrap();
+        assert!(settled_share(&get_expense(deps.as_ref(), rent), "bob"));
+        assert!(!settled_share(&get_expense(deps.as_ref(), power), "bob"));
+        assert!(!get_expense(deps.as_ref(), rent).settled);
+        
+        // The expense is settled once every share is paid off
+        execute(deps.as_mut(), env.clone(), mock_info("carol", &coins(30, "uxion")), settle(30)).unwrap();
+        assert!(get_expense(deps.as_ref(), rent).settled);
+        assert_eq!(vec![rent], expenses(deps.as_ref(), true));
+        assert_eq!(vec![power], expenses(deps.as_ref(), false));
+        
+        // Paying everything off settles the rest
+        for member in ["bob", "carol"] {
+            let owed = if member == "bob" { 10 } else { 20 };
+            let msg = ExecuteMsg::SettleAllDebts { group_id: 1 };
+            execute(deps.as_mut(), env.clone(), mock_info(member, &coins(owed, "uxion")), msg).unwrap();
+        }
+        assert!(get_expense(deps.as_ref(), power).settled);
+        assert_eq!(vec![rent, power], expenses(deps.as_ref(), true));
     }
 
     #[test]
-    fn add_expense_works() {
+    fn settlements_are_tracked_per_creditor() {
         let mut deps = mock_dependencies();
-        let mut env = mock_env();
-        env.block.time = Timestamp::from_seconds(1_000_000);
-        
-        // Initialize the contract
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "uxion"));
-        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let env = mock_env();
         
-        // Create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
         let msg = ExecuteMsg::CreateGroup { 
-            name: "Room 101 Expenses".to_string(),
-            members: vec!["member1".to_string(), "member2".to_string()]
+            name: "Flat".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
         };
-        let info = mock_info("creator", &[]);
-        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
         
-        // Add an expense
-        let msg = ExecuteMsg::AddExpense { 
-            group_id: 1,
-            description: "Groceries".to_string(),
-            amount: Uint128::new(150),
-            split_between: vec![],  // Empty means split among all members
+        let add = |deps: DepsMut, sender: &str, amount: u128, split_between: &[&str], payers: Option<Vec<Payer>>| -> u64 {
+            let msg = ExecuteMsg::AddExpense { 
+                group_id: 1,
+                description: "Shopping".to_string(),
+                amount: Uint128::new(amount),
+                split_between: split_between.iter().map(|member| member.to_string()).collect(),
+                split_mode: None,
+                category: None,
+                tags: None,
+                occurred_at: None,
+                payers,
+                paid_by: None,
+            };
+            let res = execute(deps, mock_env(), mock_info(sender, &[]), msg).unwrap();
+            res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap()
         };
-        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
-        
-        // Check attributes
-        assert_eq!(
-            vec![
-                ("action", "add_expense"),
-                ("id", "1"),
-                ("group_id", "1"),
-                ("paid_by", "creator"),
-                ("amount", "150"),
-                ("split_between", "3"),
-            ],
-            res.attributes
-        );
-        
-        // Query the expense
-        let query_msg = QueryMsg::GetExpense { id: 1 };
-        let res: ExpenseResponse = from_json(&query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
-        
-        assert_eq!(1, res.expense.id);
-        assert_eq!("Groceries", res.expense.description);
-        assert_eq!(Uint128::new(150), res.expense.amount);
-        assert_eq!(Addr::unchecked("creator"), res.expense.paid_by);
-        assert_eq!(3, res.expense.split_between.len());
+        // Alice owes carol 50 for the first expense and bob 30 for the second; the third
+        // was paid by both of them, once carol confirms her part, so she owes each 20 of it
+        let first = add(deps.as_mut(), "carol", 100, &["alice", "carol"], None);
+        let second = add(deps.as_mut(), "bob", 60, &["alice", "bob"], None);
+        let shared = add(deps.as_mut(), "bob", 40, &["alice"], Some(vec![
+            Payer { address: "bob".to_string(), amount: Uint128::new(20) },
+            Payer { address: "carol".to_string(), amount: Uint128::new(20) },
+        ]));
+        execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), ExecuteMsg::ConfirmExpense { id: shared }).unwrap();
         
-        // Check debts - member1 should owe creator 50 (150/3)
-        let query_msg = QueryMsg::GetDebts { group_id: 1 };
-        let res: DebtsResponse = from_json(&query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        let settle = |deps: DepsMut, to: &str, amount: u128| {
+            let msg = ExecuteMsg::SettleDebt { group_id: 1, to: to.to_string(), amount: Uint128::new(amount) };
+            execute(deps, mock_env(), mock_info("alice", &coins(amount, "uxion")), msg).unwrap();
+        };
+        let settled = |deps: Deps, id: u64| -> bool {
+            let res: ExpenseResponse = from_json(query(deps, mock_env(), QueryMsg::GetExpense { id }).unwrap()).unwrap();
+            res.expense.shares.iter().find(|share| share.member == Addr::unchecked("alice")).unwrap().settled
+        };
         
-        assert_eq!(2, res.debts.len());  // 2 members owe the creator
+        // Paying bob only pays off what she owed bob, oldest first
+        settle(deps.as_mut(), "bob", 30);
+        assert!(!settled(deps.as_ref(), first));
+        assert!(settled(deps.as_ref(), second));
+        assert!(!settled(deps.as_ref(), shared));
         
-        // Find debt from member1 to creator
-        let debt = res.debts.iter().find(|d| 
-            d.debtor == Addr::unchecked("member1") && 
-            d.creditor == Addr::unchecked("creator")
-        ).unwrap();
+        // A share with two payers is only paid off once both have been paid
+        settle(deps.as_mut(), "bob", 20);
+        assert!(!settled(deps.as_ref(), shared));
+        settle(deps.as_mut(), "carol", 50);
+        assert!(settled(deps.as_ref(), first));
+        assert!(!settled(deps.as_ref(), shared));
+        settle(deps.as_mut(), "carol", 20);
+        assert!(settled(deps.as_ref(), shared));
         
-        assert_eq!(Uint128::new(50), debt.amount);  // 150 / 3 = 50
+        // Debts netted against each other pay both charges off, until one of them is removed
+        let owed_to_bob = add(deps.as_mut(), "bob", 20, &["alice", "bob"], None);
+        let owed_to_alice = add(deps.as_mut(), "alice", 20, &["alice", "bob"], None);
+        assert!(settled(deps.as_ref(), owed_to_bob));
+        execute(deps.as_mut(), env, mock_info("alice", &[]), ExecuteMsg::DeleteExpense { id: owed_to_alice }).unwrap();
+        assert!(!settled(deps.as_ref(), owed_to_bob));
+        assert!(settled(deps.as_ref(), second));
     }
 
     #[test]
-    fn settle_debt_works() {
+    fn leaving_never_orphans_debts() {
         let mut deps = mock_dependencies();
-        let mut env = mock_env();
-        env.block.time = Timestamp::from_seconds(1_000_000);
-        
-        // Initialize the contract
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "uxion"));
-        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let env = mock_env();
         
-        // Create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
         let msg = ExecuteMsg::CreateGroup { 
-            name: "Room 101 Expenses".to_string(),
-            members: vec!["member1".to_string(), "member2".to_string()]
+            name: "Flat".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
         };
-        let info = mock_info("creator", &[]);
-        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
         
-        // Add an expense - creator pays
-        let msg = ExecuteMsg::AddExpense { 
+        let expense = |split_between: &[&str]| ExecuteMsg::AddExpense { 
             group_id: 1,
             description: "Groceries".to_string(),
-            amount: Uint128::new(150),
-            split_between: vec![],  // Empty means split among all members
+            amount: Uint128::new(100),
+            split_between: split_between.iter().map(|member| member.to_string()).collect(),
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
         };
-        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let get_group = |deps: Deps| -> Group {
+            let res: GroupResponse = from_json(query(deps, mock_env(), QueryMsg::GetGroup { id: 1 }).unwrap()).unwrap();
+            res.group
+        };
+        let leave = ExecuteMsg::LeaveGroup { group_id: 1 };
         
-        // member1 settles debt with creator
-        let msg = ExecuteMsg::SettleDebt { 
+        // A disputed share could still become a debt, so Carol can neither leave nor be removed
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(&["alice", "carol"])).unwrap();
+        let disputed: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        let msg = ExecuteMsg::DisputeExpense { id: disputed, reason: "Not mine".to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), msg).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), leave.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::PendingExpenses {}));
+        let msg = ExecuteMsg::RemoveMember { group_id: 1, member: "carol".to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::PendingExpenses {}));
+        
+        // Bob pays his share and leaves; deleting the expense afterwards owes him a refund
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), expense(&["alice", "bob"])).unwrap();
+        let id: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        let msg = ExecuteMsg::SettleDebt { group_id: 1, to: "alice".to_string(), amount: Uint128::new(50) };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(50, "uxion")), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), leave).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::DeleteExpense { id }).unwrap();
+        
+        // He is back as a departed member until the refund is paid
+        assert_eq!(vec![Addr::unchecked("bob")], get_group(deps.as_ref()).departed_members);
+        let res: GroupsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetUserGroups { 
+            user: "bob".to_string(), limit: None, start_after: None, include_archived: None,
+        }).unwrap()).unwrap();
+        assert_eq!(1, res.groups.len());
+        let msg = ExecuteMsg::SettleDebt { group_id: 1, to: "bob".to_string(), amount: Uint128::new(50) };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &coins(50, "uxion")), msg).unwrap();
+        assert!(get_group(deps.as_ref()).departed_members.is_empty());
+        let res: DebtsResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
+        assert!(res.debts.is_empty());
+    }
+
+    #[test]
+    fn paid_shares_cannot_be_disputed() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Flat".to_string(),
+            members: vec!["bob".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let msg = ExecuteMsg::AddExpense { 
             group_id: 1,
-            to: "creator".to_string(),
-            amount: Uint128::new(50),
+            description: "Internet".to_string(),
+            amount: Uint128::new(100),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
         };
-        let info = mock_info("member1", &coins(50, "uxion"));
-        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let id: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        let dispute = ExecuteMsg::DisputeExpense { id, reason: "Too expensive".to_string() };
         
-        // Check attributes and message
-        assert_eq!(1, res.messages.len());
+        // Even a partial payment rules out a dispute, so nothing is refunded before a ruling
+        let msg = ExecuteMsg::SettleDebt { group_id: 1, to: "alice".to_string(), amount: Uint128::new(20) };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(20, "uxion")), msg).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), dispute).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidDispute { .. }));
+        
+        let res: DebtsResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
         assert_eq!(
-            vec![
-                ("action", "settle_debt"),
-                ("group_id", "1"),
-                ("from", "member1"),
-                ("to", "creator"),
-                ("amount", "50"),
-                ("remaining_debt", "0"),
-            ],
-            res.attributes
+            vec![Debt {
+                debtor: Addr::unchecked("bob"),
+                creditor: Addr::unchecked("alice"),
+                amount: Uint128::new(30),
+            }],
+            res.debts
         );
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetExpense { id }).unwrap()).unwrap();
+        assert!(res.expense.disputes.is_empty());
+    }
+
+    #[test]
+    fn simplified_debts_keep_shares_unsettled() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
         
-        // Check debts - member1 should no longer owe creator
-        let query_msg = QueryMsg::GetDebts { group_id: 1 };
-        let res: DebtsResponse = from_json(&query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Flat".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
         
-        assert_eq!(1, res.debts.len());  // Only member2 still owes the creator
+        // Bob owes Alice 50 and Carol owes Bob 50
+        let mut ids = vec![];
+        for (payer, split_between) in [("alice", ["alice", "bob"]), ("bob", ["bob", "carol"])] {
+            let msg = ExecuteMsg::AddExpense { 
+                group_id: 1,
+                description: "Dinner".to_string(),
+                amount: Uint128::new(100),
+                split_between: split_between.iter().map(|member| member.to_string()).collect(),
+                split_mode: None,
+                category: None,
+                tags: None,
+                occurred_at: None,
+                payers: None,
+                paid_by: None,
+            };
+            let res = execute(deps.as_mut(), env.clone(), mock_info(payer, &[]), msg).unwrap();
+            ids.push(res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse::<u64>().unwrap());
+        }
+        let get_expense = |deps: Deps, id: u64| -> Expense {
+            let res: ExpenseResponse = from_json(query(deps, mock_env(), QueryMsg::GetExpense { id }).unwrap()).unwrap();
+            res.expense
+        };
         
-        // The remaining debt should be from member2 to creator
-        let debt = &res.debts[0];
-        assert_eq!(Addr::unchecked("member2"), debt.debtor);
-        assert_eq!(Addr::unchecked("creator"), debt.creditor);
-        assert_eq!(Uint128::new(50), debt.amount);
+        // Simplifying leaves Carol owing Alice directly
+        for member in ["alice", "bob", "carol"] {
+            let msg = ExecuteMsg::SetSimplifyDebtsOptIn { group_id: 1, opt_in: true };
+            execute(deps.as_mut(), env.clone(), mock_info(member, &[]), msg).unwrap();
+        }
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::SimplifyDebts { group_id: 1 }).unwrap();
+        
+        // Bob's debt was netted away, but Carol's share stays open until she pays Alice
+        assert!(get_expense(deps.as_ref(), ids[0]).settled);
+        let expense = get_expense(deps.as_ref(), ids[1]);
+        assert!(!expense.settled);
+        assert!(!expense.shares.iter().find(|share| share.member == Addr::unchecked("carol")).unwrap().settled);
+        
+        let msg = ExecuteMsg::SettleDebt { group_id: 1, to: "alice".to_string(), amount: Uint128::new(50) };
+        execute(deps.as_mut(), env, mock_info("carol", &coins(50, "uxion")), msg).unwrap();
+        assert!(get_expense(deps.as_ref(), ids[1]).settled);
     }
 
     #[test]
-    fn settle_all_debts_works() {
+    fn crediting_other_payers_needs_their_confirmation() {
         let mut deps = mock_dependencies();
-        let mut env = mock_env();
-        env.block.time = Timestamp::from_seconds(1_000_000);
-        
-        // Initialize the contract
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "uxion"));
-        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let env = mock_env();
         
-        // Create a group
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
         let msg = ExecuteMsg::CreateGroup { 
-            name: "Room 101 Expenses".to_string(),
-            members: vec!["member1".to_string(), "member2".to_string()]
+            name: "Trip".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
         };
-        let info = mock_info("creator", &[]);
-        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
         
-        // Add expense from creator
+        let payers = || Some(vec![
+            Payer { address: "bob".to_string(), amount: Uint128::new(1) },
+            Payer { address: "carol".to_string(), amount: Uint128::new(89) },
+        ]);
         let msg = ExecuteMsg::AddExpense { 
             group_id: 1,
-            description: "Groceries".to_string(),
-            amount: Uint128::new(150),
-            split_between: vec![],  // Empty means split among all members
+            description: "Hotel".to_string(),
+            amount: Uint128::new(90),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: payers(),
+            paid_by: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap();
+        let id: u64 = res.attributes.iter().find(|attr| attr.key == "id").unwrap().value.parse().unwrap();
+        let get_expense = |deps: Deps| -> Expense {
+            let res: ExpenseResponse = from_json(query(deps, mock_env(), QueryMsg::GetExpense { id }).unwrap()).unwrap();
+            res.expense
+        };
+        let debts = |deps: Deps| -> usize {
+            let res: DebtsResponse = from_json(query(deps, mock_env(), QueryMsg::GetDebts { group_id: 1 }).unwrap()).unwrap();
+            res.debts.len()
         };
-        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
         
-        // Add expense from member1
-        let msg = ExecuteMsg::AddExpense { 
-            group_id: 1,
-            description: "Utilities".to_string(),
+        // Bob paying part himself doesn't let him credit Carol with the rest
+        assert_eq!(ExpenseStatus::PendingConfirmation, get_expense(deps.as_ref()).status);
+        assert_eq!(0, debts(deps.as_ref()));
+        let res: ExpensesResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetPendingExpenses { 
+            user: "carol".to_string(), group_id: Some(1), limit: None, start_after: None,
+        }).unwrap()).unwrap();
+        assert_eq!(vec![id], res.expenses.iter().map(|expense| expense.id).collect::<Vec<_>>());
+        
+        // Only Carol can confirm her part
+        let confirm = ExecuteMsg::ConfirmExpense { id };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), confirm.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::AlreadyConfirmed {}));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), confirm.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), confirm).unwrap();
+        assert_eq!(ExpenseStatus::Active, get_expense(deps.as_ref()).status);
+        assert_eq!(3, debts(deps.as_ref()));
+        
+        // Changing what Carol is credited with asks her again; leaving it alone doesn't
+        let edit = |description: &str, payers: Option<Vec<Payer>>| ExecuteMsg::EditExpense { 
+            id,
+            description: description.to_string(),
             amount: Uint128::new(90),
-            split_between: vec![],  // Empty means split among all members
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers,
         };
-        let info = mock_info("member1", &[]);
-        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), edit("Hotel, two nights", None)).unwrap();
+        assert_eq!(ExpenseStatus::Active, get_expense(deps.as_ref()).status);
+        let more = Some(vec![
+            Payer { address: "bob".to_string(), amount: Uint128::new(10) },
+            Payer { address: "carol".to_string(), amount: Uint128::new(80) },
+        ]);
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), edit("Hotel, two nights", more)).unwrap();
+        assert_eq!(ExpenseStatus::PendingConfirmation, get_expense(deps.as_ref()).status);
+        assert_eq!(0, debts(deps.as_ref()));
         
-        // Now member2 owes both creator and member1
-        // member2 settles all debts at once
-        let msg = ExecuteMsg::SettleAllDebts { group_id: 1 };
-        let info = mock_info("member2", &coins(80, "uxion"));  // 50 to creator + 30 to member1
-        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        // Once pending, only Carol can clear it, even after an admin's edit
+        execute(deps.as_mut(), env, mock_info("alice", &[]), edit("Hotel", payers())).unwrap();
+        assert_eq!(ExpenseStatus::PendingConfirmation, get_expense(deps.as_ref()).status);
+    }
+
+    #[test]
+    fn admins_can_change_remainder_policy() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
         
-        // Check messages - should be 2 BankMsg::Send
-        assert_eq!(2, res.messages.len());
+        instantiate(deps.as_mut(), env.clone(), mock_info("alice", &[]), InstantiateMsg::default()).unwrap();
+        let msg = ExecuteMsg::CreateGroup { 
+            name: "Trip".to_string(),
+            members: vec!["bob".to_string(), "carol".to_string()],
+            remainder_policy: None,
+            denom: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
         
-        // Check there are no more debts for member2
-        let query_msg = QueryMsg::GetBalanceSummary { 
+        // Only admins can change it
+        let update = ExecuteMsg::UpdateRemainderPolicy { group_id: 1, remainder_policy: RemainderPolicy::Payer };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), update.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), update).unwrap();
+        let res: GroupResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetGroup { id: 1 }).unwrap()).unwrap();
+        assert_eq!(RemainderPolicy::Payer, res.group.remainder_policy);
+        
+        // The next split leaves the extra unit with the payer
+        let msg = ExecuteMsg::AddExpense { 
             group_id: 1,
-            user: "member2".to_string()
+            description: "Taxi".to_string(),
+            amount: Uint128::new(100),
+            split_between: vec![],
+            split_mode: None,
+            category: None,
+            tags: None,
+            occurred_at: None,
+            payers: None,
+            paid_by: None,
         };
-        let res: BalanceSummaryResponse = from_json(&query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
-        
-        assert_eq!(Uint128::zero(), res.total_owed);
-        assert_eq!(0, res.balances.len());  // No balances should exist after settling all debts
+        execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), msg).unwrap();
+        let res: ExpenseResponse = from_json(query(deps.as_ref(), env, QueryMsg::GetExpense { id: 1 }).unwrap()).unwrap();
+        let shares: Vec<(&str, Uint128)> = res.expense.shares.iter().map(|share| (share.member.as_str(), share.amount)).collect();
+        assert_eq!(vec![("alice", Uint128::new(33)), ("bob", Uint128::new(33)), ("carol", Uint128::new(34))], shares);
     }
 }
@@ -12,24 +12,99 @@ pub enum ContractError {
     #[error("Group not found")]
     GroupNotFound {},
     
+    #[error("Group is archived")]
+    GroupArchived {},
+    
+    #[error("Group is closed")]
+    GroupClosed {},
+    
     #[error("Expense not found")]
     ExpenseNotFound {},
     
+    #[error("Expense is not waiting for confirmation or approval")]
+    ExpenseNotPending {},
+    
+    #[error("Expense already approved")]
+    AlreadyApproved {},
+    
+    #[error("Expense already confirmed")]
+    AlreadyConfirmed {},
+    
+    #[error("Invalid dispute: {reason}")]
+    InvalidDispute { reason: String },
+    
+    #[error("Invalid approval policy: {reason}")]
+    InvalidApprovalPolicy { reason: String },
+    
     #[error("Invalid group name: {reason}")]
     InvalidGroupName { reason: String },
     
+    #[error("Invalid group metadata: {reason}")]
+    InvalidGroupMetadata { reason: String },
+    
     #[error("Invalid expense description: {reason}")]
     InvalidExpenseDescription { reason: String },
     
+    #[error("Invalid expense tags: {reason}")]
+    InvalidExpenseTags { reason: String },
+    
+    #[error("Invalid payers: {reason}")]
+    InvalidPayers { reason: String },
+    
+    #[error("Invalid expense date: {reason}")]
+    InvalidExpenseDate { reason: String },
+    
+    #[error("Category {category} is not defined for this group")]
+    UnknownCategory { category: String },
+    
     #[error("Invalid amount: {reason}")]
     InvalidAmount { reason: String },
     
+    #[error("Invalid split: {reason}")]
+    InvalidSplit { reason: String },
+    
+    #[error("Invalid denom {denom}: {reason}")]
+    InvalidDenom { denom: String, reason: String },
+    
+    #[error("Token {token} is not accepted by this group")]
+    UnsupportedToken { token: String },
+    
+    #[error("Group has outstanding debts")]
+    OutstandingDebts {},
+    
+    #[error("Member has expenses that are pending or disputed")]
+    PendingExpenses {},
+    
+    #[error("Invalid role: {reason}")]
+    InvalidRole { reason: String },
+    
+    #[error("No ownership transfer is pending for this user")]
+    NoPendingOwnership {},
+    
+    #[error("Ownership transfer has expired")]
+    OwnershipTransferExpired {},
+    
     #[error("User not in group")]
     UserNotInGroup {},
     
     #[error("User already in group")]
     UserAlreadyInGroup {},
     
+    #[error("User has not been invited to this group")]
+    NotInvited {},
+    
+    #[error("User has already been invited to this group")]
+    AlreadyInvited {},
+    
+    #[error("Invalid join code: {reason}")]
+    InvalidJoinCode { reason: String },
+    
+    #[error("A request to join this group is already pending")]
+    JoinRequestPending {},
+    
+    #[error("Join request not found")]
+    JoinRequestNotFound {},
+    
     #[error("Insufficient funds: needed {needed}, had {available}")]
     InsufficientFunds { needed: String, available: String },
     
@@ -42,6 +117,12 @@ pub enum ContractError {
     #[error("Cannot settle with yourself")]
     CannotSettleWithSelf {},
     
+    #[error("Every group member must opt in before debts can be simplified")]
+    SimplificationNotApproved {},
+    
+    #[error("Invalid migration: {reason}")]
+    InvalidMigration { reason: String },
+    
     #[error("Custom Error val: {val:?}")]
     CustomError { val: String },
 }
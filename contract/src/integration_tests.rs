@@ -1,11 +1,12 @@
 #[cfg(test)]
 mod tests {
-    use crate::helpers::CwTemplateContract;
-    use crate::msg::InstantiateMsg;
-    use cosmwasm_std::{Addr, Coin, Empty, Uint128};
-    use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor};
+    use crate::msg::{DebtsResponse, ExecuteMsg, InstantiateMsg, QueryMsg, ReceiveMsg};
+    use crate::ContractError;
+    use cosmwasm_std::{to_json_binary, Addr, Empty, Uint128};
+    use cw20::{BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg};
+    use cw_multi_test::{App, Contract, ContractWrapper, Executor};
 
-    pub fn contract_template() -> Box<dyn Contract<Empty>> {
+    pub fn contract_expense_splitter() -> Box<dyn Contract<Empty>> {
         let contract = ContractWrapper::new(
             crate::contract::execute,
             crate::contract::instantiate,
@@ -14,58 +15,167 @@ mod tests {
         Box::new(contract)
     }
 
-    const USER: &str = "USER";
-    const ADMIN: &str = "ADMIN";
-    const NATIVE_DENOM: &str = "denom";
-
-    fn mock_app() -> App {
-        AppBuilder::new().build(|router, _, storage| {
-            router
-                .bank
-                .init_balance(
-                    storage,
-                    &Addr::unchecked(USER),
-                    vec![Coin {
-                        denom: NATIVE_DENOM.to_string(),
-                        amount: Uint128::new(1),
-                    }],
-                )
-                .unwrap();
-        })
+    pub fn contract_cw20() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            cw20_base::contract::execute,
+            cw20_base::contract::instantiate,
+            cw20_base::contract::query,
+        );
+        Box::new(contract)
     }
 
-    fn proper_instantiate() -> (App, CwTemplateContract) {
-        let mut app = mock_app();
-        let cw_template_id = app.store_code(contract_template());
+    const CREATOR: &str = "creator";
+    const MEMBER: &str = "member1";
 
-        let msg = InstantiateMsg { count: 1i32 };
-        let cw_template_contract_addr = app
+    fn instantiate_token(app: &mut App, symbol: &str) -> Addr {
+        let cw20_id = app.store_code(contract_cw20());
+        let msg = cw20_base::msg::InstantiateMsg {
+            name: format!("{} Stablecoin", symbol),
+            symbol: symbol.to_string(),
+            decimals: 6,
+            initial_balances: vec![Cw20Coin {
+                address: MEMBER.to_string(),
+                amount: Uint128::new(1000),
+            }],
+            mint: None,
+            marketing: None,
+        };
+        app.instantiate_contract(cw20_id, Addr::unchecked(CREATOR), &msg, &[], symbol, None)
+            .unwrap()
+    }
+
+    // Sets up a group where MEMBER owes CREATOR 50 and the group accepts `token`
+    fn proper_instantiate() -> (App, Addr, Addr) {
+        let mut app = App::default();
+        let token = instantiate_token(&mut app, "USDC");
+
+        let splitter_id = app.store_code(contract_expense_splitter());
+        let splitter = app
             .instantiate_contract(
-                cw_template_id,
-                Addr::unchecked(ADMIN),
-                &msg,
+                splitter_id,
+                Addr::unchecked(CREATOR),
+                &InstantiateMsg::default(),
                 &[],
-                "test",
+                "expense-splitter",
                 None,
             )
             .unwrap();
 
-        let cw_template_contract = CwTemplateContract(cw_template_contract_addr);
+        let creator = Addr::unchecked(CREATOR);
+        let msgs = vec![
+            ExecuteMsg::CreateGroup {
+                name: "Team".to_string(),
+                members: vec![MEMBER.to_string()],
+                remainder_policy: None,
+                denom: None,
+            },
+            ExecuteMsg::UpdateCw20Token {
+                group_id: 1,
+                token: Some(token.to_string()),
+            },
+            ExecuteMsg::AddExpense {
+                group_id: 1,
+                description: "Team lunch".to_string(),
+                amount: Uint128::new(100),
+                split_between: vec![],
+                split_mode: None,
+                category: None,
+                tags: None,
+                occurred_at: None,
+                payers: None,
+                paid_by: None,
+            },
+        ];
+        for msg in msgs {
+            app.execute_contract(creator.clone(), splitter.clone(), &msg, &[])
+                .unwrap();
+        }
 
-        (app, cw_template_contract)
+        (app, splitter, token)
     }
 
-    mod count {
+    fn token_balance(app: &App, token: &Addr, address: &str) -> Uint128 {
+        let res: BalanceResponse = app
+            .wrap()
+            .query_wasm_smart(
+                token,
+                &Cw20QueryMsg::Balance {
+                    address: address.to_string(),
+                },
+            )
+            .unwrap();
+        res.balance
+    }
+
+    fn send_msg(splitter: &Addr, amount: u128, msg: &ReceiveMsg) -> Cw20ExecuteMsg {
+        Cw20ExecuteMsg::Send {
+            contract: splitter.to_string(),
+            amount: Uint128::new(amount),
+            msg: to_json_binary(msg).unwrap(),
+        }
+    }
+
+    mod cw20_settlement {
         use super::*;
-        use crate::msg::ExecuteMsg;
 
         #[test]
-        fn count() {
-            let (mut app, cw_template_contract) = proper_instantiate();
+        fn settle_debt_with_cw20() {
+            let (mut app, splitter, token) = proper_instantiate();
+
+            let msg = ReceiveMsg::SettleDebt {
+                group_id: 1,
+                to: CREATOR.to_string(),
+            };
+            let send = send_msg(&splitter, 20, &msg);
+            app.execute_contract(Addr::unchecked(MEMBER), token.clone(), &send, &[])
+                .unwrap();
+
+            // The creditor receives the tokens and the debt goes down
+            assert_eq!(Uint128::new(20), token_balance(&app, &token, CREATOR));
+            assert_eq!(Uint128::new(980), token_balance(&app, &token, MEMBER));
+            assert_eq!(Uint128::zero(), token_balance(&app, &token, splitter.as_str()));
+
+            let res: DebtsResponse = app
+                .wrap()
+                .query_wasm_smart(&splitter, &QueryMsg::GetDebts { group_id: 1 })
+                .unwrap();
+            assert_eq!(Uint128::new(30), res.debts[0].amount);
+
+            // Paying the rest through SettleAllDebts clears the debt and refunds the surplus
+            let msg = ReceiveMsg::SettleAllDebts { group_id: 1 };
+            let send = send_msg(&splitter, 45, &msg);
+            app.execute_contract(Addr::unchecked(MEMBER), token.clone(), &send, &[])
+                .unwrap();
+
+            assert_eq!(Uint128::new(50), token_balance(&app, &token, CREATOR));
+            assert_eq!(Uint128::new(950), token_balance(&app, &token, MEMBER));
+            assert_eq!(Uint128::zero(), token_balance(&app, &token, splitter.as_str()));
+            let res: DebtsResponse = app
+                .wrap()
+                .query_wasm_smart(&splitter, &QueryMsg::GetDebts { group_id: 1 })
+                .unwrap();
+            assert!(res.debts.is_empty());
+        }
+
+        #[test]
+        fn other_tokens_are_rejected() {
+            let (mut app, splitter, _) = proper_instantiate();
+            let other = instantiate_token(&mut app, "FAKE");
+
+            let msg = ReceiveMsg::SettleDebt {
+                group_id: 1,
+                to: CREATOR.to_string(),
+            };
+            let send = send_msg(&splitter, 50, &msg);
+            let err = app
+                .execute_contract(Addr::unchecked(MEMBER), other.clone(), &send, &[])
+                .unwrap_err();
 
-            let msg = ExecuteMsg::Increment {};
-            let cosmos_msg = cw_template_contract.call(msg).unwrap();
-            app.execute(Addr::unchecked(USER), cosmos_msg).unwrap();
+            assert!(matches!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::UnsupportedToken { .. }
+            ));
+            assert_eq!(Uint128::new(1000), token_balance(&app, &other, MEMBER));
         }
     }
 }
@@ -1,11 +1,12 @@
 pub mod contract;
 pub mod error;
+mod integration_tests;
 pub mod msg;
 pub mod state;
 
+pub use crate::error::ContractError;
+
 #[cfg(test)]
 mod tests {
     // We'll include tests in the individual modules
 }
-
-pub use crate::error::ContractError;
@@ -1,21 +1,44 @@
+use std::collections::BTreeMap;
+
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Uint128};
-use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use cosmwasm_std::{Addr, Int128, Timestamp, Uint128};
+use cw20::Cw20ReceiveMsg;
 
-use crate::state::{Group, Expense, Debt};
+use crate::state::{ApprovalPolicy, Config, ExpenseStatus, Group, GroupPrivacy, GroupRole, Invite, JoinCode, JoinRequest, PendingOwnership, Expense, Debt, ExpenseRevision, RemainderPolicy, Share, SplitMode};
 
+// Every field is optional and falls back to the contract defaults
 #[cw_serde]
+#[derive(Default)]
 pub struct InstantiateMsg {
-    // No specific initialization parameters needed
+    pub owner: Option<String>, // Defaults to the instantiator
+    pub default_denom: Option<String>,
+    pub max_group_members: Option<u32>,
+    pub max_group_name_length: Option<u32>,
+    pub max_expense_description_length: Option<u32>,
+}
+
+#[cw_serde]
+pub struct MigrateMsg {
+    pub owner: Option<String>, // Required when migrating from a version without a config
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
+    // Change the contract config (contract owner only); omitted fields are left unchanged
+    UpdateConfig {
+        owner: Option<String>,
+        default_denom: Option<String>,
+        max_group_members: Option<u32>,
+        max_group_name_length: Option<u32>,
+        max_expense_description_length: Option<u32>,
+    },
+    
     // Create a new expense group
     CreateGroup { 
         name: String,
         members: Vec<String>, // List of member addresses
+        remainder_policy: Option<RemainderPolicy>, // Defaults to round robin
+        denom: Option<String>, // Settlement denom, defaults to uxion
     },
     
     // Add an expense to a group
@@ -24,6 +47,53 @@ pub enum ExecuteMsg {
         description: String,
         amount: Uint128,
         split_between: Vec<String>, // Defaults to all group members if empty
+        split_mode: Option<SplitMode>, // Defaults to an equal split
+        category: Option<String>, // Must be one of the group's categories
+        tags: Option<Vec<String>>,
+        occurred_at: Option<Timestamp>, // Defaults to the block time
+        payers: Option<Vec<Payer>>, // Must include paid_by and sum to the amount; defaults to paid_by paying it all
+        paid_by: Option<String>, // Defaults to the sender; pending until the payer confirms unless the sender is an admin
+    },
+    
+    // Replace the details of an expense (payer, recorder or a group admin only)
+    EditExpense {
+        id: u64,
+        description: String,
+        amount: Uint128,
+        split_between: Vec<String>, // Defaults to all group members if empty
+        split_mode: Option<SplitMode>, // Defaults to an equal split
+        category: Option<String>, // Must be one of the group's categories
+        tags: Option<Vec<String>>,
+        occurred_at: Option<Timestamp>, // Left unchanged if omitted
+        payers: Option<Vec<Payer>>, // Must include the original payer; kept if omitted, unless only they paid
+    },
+    
+    // Confirm what an expense someone else recorded credits the sender with; it joins the group's debts once every payer has
+    ConfirmExpense {
+        id: u64,
+    },
+    
+    // Approve an expense that charges the sender
+    ApproveExpense {
+        id: u64,
+    },
+    
+    // Object to an expense that charges the sender. Before approval this holds the expense back;
+    // afterwards the sender's share is frozen out of the debts until the dispute is resolved.
+    DisputeExpense {
+        id: u64,
+        reason: String,
+    },
+    
+    // Close the open disputes on an expense (group admins only; the member who raised a dispute can withdraw it by upholding)
+    ResolveDispute {
+        id: u64,
+        resolution: DisputeResolution,
+    },
+    
+    // Remove an expense and the debts it created (payer, recorder or a group admin only)
+    DeleteExpense {
+        id: u64,
     },
     
     // Settle a debt (pay money to another user)
@@ -33,37 +103,262 @@ pub enum ExecuteMsg {
         amount: Uint128,
     },
     
-    // Mark all expenses as settled in a group
+    // Pay off everything the sender owes in a group
     SettleAllDebts {
         group_id: u64,
     },
     
-    // Join an existing group
+    // Settle debts with CW20 tokens; the inner message is a ReceiveMsg
+    Receive(Cw20ReceiveMsg),
+    
+    // Accept (or stop accepting) a CW20 token for settlement (group admin only, requires no outstanding debts)
+    UpdateCw20Token {
+        group_id: u64,
+        token: Option<String>,
+    },
+    
+    // Change the denom debts are settled in (group admin only, requires no outstanding debts)
+    UpdateSettlementDenom {
+        group_id: u64,
+        denom: String,
+    },
+    
+    // Agree (or stop agreeing) to have the group's debts rewritten into the simplified set
+    SetSimplifyDebtsOptIn {
+        group_id: u64,
+        opt_in: bool,
+    },
+    
+    // Replace the group's debts with the minimal set of transfers (requires every member to opt in)
+    SimplifyDebts {
+        group_id: u64,
+    },
+    
+    // Give a member the admin, member or viewer role (only the owner can grant or take away admin)
+    GrantRole {
+        group_id: u64,
+        member: String,
+        role: GroupRole,
+    },
+    
+    // Return a member to the regular member role
+    RevokeRole {
+        group_id: u64,
+        member: String,
+    },
+    
+    // Change who can join the group (group admins only)
+    UpdateGroupPrivacy {
+        group_id: u64,
+        privacy: GroupPrivacy,
+    },
+    
+    // Change whose approval new expenses need before they count (group admins only)
+    UpdateApprovalPolicy {
+        group_id: u64,
+        policy: ApprovalPolicy,
+    },
+    
+    // Change who absorbs the leftover units of later splits (group admins only)
+    UpdateRemainderPolicy {
+        group_id: u64,
+        remainder_policy: RemainderPolicy,
+    },
+    
+    // Invite a user to the group (group admins only); they accept by joining
+    InviteMember {
+        group_id: u64,
+        invitee: String,
+    },
+    
+    // Withdraw an invite (group admins), or decline it (the invitee)
+    RevokeInvite {
+        group_id: u64,
+        invitee: String,
+    },
+    
+    // Let a user who asked to join into the group (group admins only)
+    ApproveJoinRequest {
+        group_id: u64,
+        requester: String,
+    },
+    
+    // Turn down a request to join (group admins only)
+    RejectJoinRequest {
+        group_id: u64,
+        requester: String,
+    },
+    
+    // Register a join code by the public half of a secp256k1 key pair whose secret key is
+    // shared with the people invited (group admins only)
+    CreateJoinCode {
+        group_id: u64,
+        public_key: String,            // Hex-encoded, compressed or uncompressed
+        expires_at: Option<Timestamp>, // Never expires if omitted
+        max_uses: Option<u32>,         // Unlimited if omitted
+    },
+    
+    // Stop accepting a join code (group admins only)
+    RevokeJoinCode {
+        public_key: String,
+    },
+    
+    // Join the group a code belongs to, regardless of the group's privacy. The secret key
+    // never goes on chain: the sender proves they hold it by signing the SHA-256 hash of
+    // their own address, which is of no use to anyone else.
+    JoinGroupWithCode {
+        public_key: String,
+        signature: String, // Hex-encoded 64-byte secp256k1 signature
+    },
+    
+    // Change a group's name and metadata (group admins only); omitted fields are left unchanged,
+    // empty strings clear the optional ones and `tags` replaces all existing tags
+    UpdateGroup {
+        group_id: u64,
+        name: Option<String>,
+        description: Option<String>,
+        currency_label: Option<String>,
+        avatar_uri: Option<String>,
+        tags: Option<BTreeMap<String, String>>,
+        categories: Option<Vec<String>>, // Replaces the list expenses can be filed under
+    },
+    
+    // Offer ownership of the group to another member (owner only); replaces any earlier offer
+    ProposeOwnershipTransfer {
+        group_id: u64,
+        new_owner: String,
+        expires_at: Option<Timestamp>, // Never expires if omitted
+    },
+    
+    // Take over a group whose ownership was offered to the sender; the previous owner becomes an admin
+    AcceptOwnership {
+        group_id: u64,
+    },
+    
+    // Withdraw a pending ownership offer (owner only)
+    CancelOwnershipTransfer {
+        group_id: u64,
+    },
+    
+    // Freeze a group against new expenses and members (group admins only, requires no outstanding debts)
+    ArchiveGroup {
+        group_id: u64,
+    },
+    
+    // Make an archived group active again (group admins only)
+    ReopenGroup {
+        group_id: u64,
+    },
+    
+    // Remove a member from the group (group admins only; only the owner can remove admins).
+    // A member with open debts stays a departed member until those debts are settled.
+    RemoveMember {
+        group_id: u64,
+        member: String,
+    },
+    
+    // Join an existing group, or ask to join one that requires approval
     JoinGroup {
         group_id: u64,
     },
     
-    // Leave a group
+    // Leave a group (the owner can only leave as the last member)
     LeaveGroup {
         group_id: u64,
     },
 }
 
+// What to do with a disputed expense
+#[cw_serde]
+pub enum DisputeResolution {
+    Uphold {}, // Keep the expense as it is
+    Amend {    // Change what the expense charges; the other details are kept
+        amount: Uint128,
+        split_between: Vec<String>, // Defaults to all group members if empty
+        split_mode: Option<SplitMode>,
+        payers: Option<Vec<Payer>>, // Kept if omitted, unless only the payer paid
+    },
+    Cancel {}, // Remove the expense
+}
+
+// A member who paid part of an expense
+#[cw_serde]
+pub struct Payer {
+    pub address: String,
+    pub amount: Uint128,
+}
+
+// Messages that can be sent along with CW20 tokens
+#[cw_serde]
+pub enum ReceiveMsg {
+    // Settle a debt with the received tokens
+    SettleDebt {
+        group_id: u64,
+        to: String, // Address to pay
+    },
+    
+    // Settle all of the sender's debts in a group with the received tokens
+    SettleAllDebts {
+        group_id: u64,
+    },
+}
+
 #[cw_serde]
 #[derive(QueryResponses)]
 pub enum QueryMsg {
+    // Get the contract config
+    #[returns(ConfigResponse)]
+    GetConfig {},
+    
     // Get a specific group by ID
     #[returns(GroupResponse)]
     GetGroup { 
         id: u64 
     },
     
+    // Get the role of every member of a group
+    #[returns(GroupRolesResponse)]
+    GetGroupRoles {
+        group_id: u64
+    },
+    
+    // Get the pending ownership transfer for a group, if any
+    #[returns(PendingOwnershipResponse)]
+    GetPendingOwnership {
+        group_id: u64
+    },
+    
+    // Get the pending invites for a user
+    #[returns(InvitesResponse)]
+    GetUserInvites {
+        user: String,
+    },
+    
+    // Get the pending invites for a group
+    #[returns(InvitesResponse)]
+    GetGroupInvites {
+        group_id: u64
+    },
+    
+    // Get the pending requests to join a group
+    #[returns(JoinRequestsResponse)]
+    GetJoinRequests {
+        group_id: u64
+    },
+    
+    // Get the join codes of a group that can still be used
+    #[returns(JoinCodesResponse)]
+    GetJoinCodes {
+        group_id: u64
+    },
+    
     // Get all groups a user belongs to
     #[returns(GroupsResponse)]
     GetUserGroups { 
         user: String,
         limit: Option<u32>,
         start_after: Option<u64>,
+        include_archived: Option<bool>, // Defaults to true
     },
     
     // Get a specific expense
@@ -72,12 +367,37 @@ pub enum QueryMsg {
         id: u64 
     },
     
-    // Get all expenses for a group
+    // Get the expenses waiting for a user to confirm, approve or (as an admin) resolve disputes on
+    #[returns(ExpensesResponse)]
+    GetPendingExpenses {
+        user: String,
+        group_id: Option<u64>, // All of the user's groups if omitted
+        limit: Option<u32>,
+        start_after: Option<u64>, // Expense ID to continue after
+    },
+    
+    // Get the previous versions of an edited or deleted expense
+    #[returns(ExpenseHistoryResponse)]
+    GetExpenseHistory {
+        id: u64
+    },
+    
+    // Get all expenses for a group, oldest first by the chosen date
     #[returns(ExpensesResponse)]
     GetGroupExpenses { 
         group_id: u64,
         limit: Option<u32>,
-        start_after: Option<u64>,
+        start_after: Option<u64>, // Expense ID to continue after
+        filter: Option<ExpenseFilter>,
+    },
+    
+    // Get how much a group spent per category, optionally limited to expenses dated in [from, to)
+    #[returns(CategoryTotalsResponse)]
+    GetCategoryTotals {
+        group_id: u64,
+        from: Option<Timestamp>,
+        to: Option<Timestamp>,
+        date: Option<ExpenseDate>, // Defaults to the recording time
     },
     
     // Get all debts between users in a group
@@ -86,6 +406,12 @@ pub enum QueryMsg {
         group_id: u64 
     },
     
+    // Get the smallest set of transfers that settles everyone in a group
+    #[returns(SimplifiedDebtsResponse)]
+    GetSimplifiedDebts {
+        group_id: u64
+    },
+    
     // Get summary of balances for a user in a group
     #[returns(BalanceSummaryResponse)]
     GetBalanceSummary { 
@@ -94,12 +420,68 @@ pub enum QueryMsg {
     },
 }
 
+// Which of an expense's dates a query sorts and filters by
+#[cw_serde]
+#[derive(Copy, Default)]
+pub enum ExpenseDate {
+    #[default]
+    Recorded, // When the expense was added to the contract
+    Occurred, // When the expense happened, as given by the payer
+}
+
+// Narrows down and orders the expenses returned by GetGroupExpenses
+#[cw_serde]
+#[derive(Default)]
+pub struct ExpenseFilter {
+    pub date: Option<ExpenseDate>, // Defaults to the recording time
+    pub from: Option<Timestamp>,   // Inclusive
+    pub to: Option<Timestamp>,     // Exclusive
+    pub status: Option<ExpenseStatus>,
+    pub settled: Option<bool>,
+}
+
 // Response types
+#[cw_serde]
+pub struct ConfigResponse {
+    pub config: Config,
+}
+
 #[cw_serde]
 pub struct GroupResponse {
     pub group: Group,
 }
 
+#[cw_serde]
+pub struct MemberRole {
+    pub member: Addr,
+    pub role: GroupRole,
+}
+
+#[cw_serde]
+pub struct GroupRolesResponse {
+    pub roles: Vec<MemberRole>, // In group member order
+}
+
+#[cw_serde]
+pub struct PendingOwnershipResponse {
+    pub pending: Option<PendingOwnership>,
+}
+
+#[cw_serde]
+pub struct InvitesResponse {
+    pub invites: Vec<Invite>,
+}
+
+#[cw_serde]
+pub struct JoinRequestsResponse {
+    pub requests: Vec<JoinRequest>,
+}
+
+#[cw_serde]
+pub struct JoinCodesResponse {
+    pub codes: Vec<JoinCode>,
+}
+
 #[cw_serde]
 pub struct GroupsResponse {
     pub groups: Vec<Group>,
@@ -108,6 +490,7 @@ pub struct GroupsResponse {
 #[cw_serde]
 pub struct ExpenseResponse {
     pub expense: Expense,
+    pub frozen_shares: Vec<Share>, // Shares held out of the debts by open disputes
 }
 
 #[cw_serde]
@@ -115,17 +498,39 @@ pub struct ExpensesResponse {
     pub expenses: Vec<Expense>,
 }
 
+#[cw_serde]
+pub struct ExpenseHistoryResponse {
+    pub revisions: Vec<ExpenseRevision>,
+}
+
+#[cw_serde]
+pub struct CategoryTotal {
+    pub category: Option<String>, // None for expenses without a category
+    pub amount: Uint128,
+    pub expenses: u32,            // Number of expenses counted
+}
+
+#[cw_serde]
+pub struct CategoryTotalsResponse {
+    pub totals: Vec<CategoryTotal>, // Uncategorized first, then by category name
+}
+
 #[cw_serde]
 pub struct DebtsResponse {
     pub debts: Vec<Debt>,
 }
 
+#[cw_serde]
+pub struct SimplifiedDebtsResponse {
+    pub transfers: Vec<Debt>,
+    pub opted_in: Vec<Addr>, // Members who agreed to replace the stored debts with these transfers
+}
+
 // Individual balance with another user
 #[cw_serde]
 pub struct Balance {
     pub other_user: Addr,
-    pub amount: Uint128,  // Always a positive amount 
-    pub direction: i8,    // 1 if user is owed money, -1 if user owes money
+    pub amount: Int128,   // Positive if user is owed money, negative if user owes money
 }
 
 #[cw_serde]
@@ -133,5 +538,5 @@ pub struct BalanceSummaryResponse {
     pub balances: Vec<Balance>,
     pub total_owed: Uint128,      // Total amount user owes others
     pub total_owed_to: Uint128,   // Total amount owed to user
-    pub net_balance: Uint128,     // Net balance (total_owed_to - total_owed, or 0 if negative)
+    pub net_balance: Int128,      // total_owed_to - total_owed, negative if user is a net debtor
 }
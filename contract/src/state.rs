@@ -1,15 +1,151 @@
-use cosmwasm_std::{Addr, Timestamp, Uint128};
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{Addr, Empty, Timestamp, Uint128};
 use cw_storage_plus::{Item, Map};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+// Decides who absorbs the indivisible units left over when an expense is split
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RemainderPolicy {
+    Payer,      // The payer's own share absorbs the leftover (round robin if the payer isn't splitting)
+    RoundRobin, // One unit per member in split order, starting at a different member for each expense
+}
+// Contract-wide settings, managed by the owner
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Config {
+    pub owner: Addr,
+    pub default_denom: String, // Settlement denom for groups that don't pick one
+    pub max_group_members: u32,
+    pub max_group_name_length: u32,
+    pub max_expense_description_length: u32,
+}
 // Represents a single group of users who share expenses
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct Group {
     pub id: u64,
     pub name: String,
-    pub creator: Addr,
+    pub creator: Addr, // The group's owner: whoever created it, unless ownership was transferred
     pub members: Vec<Addr>,
     pub created_at: Timestamp,
+    pub remainder_policy: RemainderPolicy,
+    pub denom: String, // Native denom debts are settled in
+    pub cw20_token: Option<Addr>, // CW20 token also accepted for settlement
+    #[serde(default)]
+    pub privacy: GroupPrivacy,    // Who can join without being let in
+    #[serde(default)]
+    pub departed_members: Vec<Addr>, // Removed members who still have debts to settle
+    #[serde(default)]
+    pub status: GroupStatus,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub currency_label: Option<String>, // Display label for amounts, e.g. "USDC"
+    #[serde(default)]
+    pub avatar_uri: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>, // Free-form key/value metadata
+    #[serde(default)]
+    pub categories: Vec<String>,        // Categories expenses can be filed under
+    #[serde(default)]
+    pub approval_policy: ApprovalPolicy, // Whose consent new expenses need before they count
+}
+// Where a group is in its lifecycle
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupStatus {
+    #[default]
+    Active,   // In normal use
+    Archived, // Frozen: kept for its history, but no new expenses or members
+    Closed,   // Everyone left; only the history and any departed members' debts remain
+}
+// Controls how new members get into a group
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupPrivacy {
+    #[default]
+    Open,             // Anyone can join
+    InviteOnly,       // Only invited users can join
+    ApprovalRequired, // Invited users join directly, anyone else asks an admin for approval
+}
+// Who has to approve an expense before its shares become debts. Only members who are
+// charged something, other than the payer, are asked; whoever adds the expense approves it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalPolicy {
+    #[default]
+    None,                           // Expenses count as soon as they're added
+    AllParticipants,                // Every charged member must approve
+    Quorum { basis_points: u32 },   // This share of the charged members, rounded up, must approve
+}
+// A standing invitation for a user to join a group
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Invite {
+    pub group_id: u64,
+    pub invitee: Addr,
+    pub invited_by: Addr,
+    pub created_at: Timestamp,
+}
+// A proposed change of group owner, waiting for the new owner to accept
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PendingOwnership {
+    pub group_id: u64,
+    pub new_owner: Addr,
+    pub proposed_by: Addr,
+    pub proposed_at: Timestamp,
+    pub expires_at: Option<Timestamp>,
+}
+// A shareable code that lets whoever holds its secret key join a group.
+// Only the public key is stored; joiners prove they hold the key by signing their own address.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct JoinCode {
+    pub group_id: u64,
+    pub public_key: String,           // Hex-encoded secp256k1 public key
+    pub created_by: Addr,
+    pub created_at: Timestamp,
+    pub expires_at: Option<Timestamp>,
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+}
+// A request to join a group, waiting for an admin's decision
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct JoinRequest {
+    pub group_id: u64,
+    pub requester: Addr,
+    pub requested_at: Timestamp,
+}
+// What a member is allowed to do in a group
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupRole {
+    Owner,  // The group creator: everything an admin can do, plus managing admins
+    Admin,  // Manages members, expenses and group settings
+    Member, // Adds expenses and settles debts
+    Viewer, // Read-only: can settle what they owe but is never charged for new expenses
+}
+// How an expense is divided among the members in split_between.
+// Values in the non-equal modes are positional: the n-th value belongs to the n-th member.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitMode {
+    Equal {},
+    Exact { amounts: Vec<Uint128> },       // Must sum to the expense amount
+    Percentage { basis_points: Vec<u32> }, // Must sum to 10000 (100%)
+    Shares { weights: Vec<u32> },          // Split proportionally to the weights
+}
+// The portion of an expense a single member is responsible for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Share {
+    pub member: Addr,
+    pub amount: Uint128,
+    #[serde(default)]
+    pub settled: bool, // Whether the member no longer owes anything for this share
+}
+// How much of an expense a single member paid
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Contribution {
+    pub payer: Addr,
+    pub amount: Uint128,
 }
 // Represents a single expense posted by a user
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
@@ -20,8 +156,80 @@ pub struct Expense {
     pub amount: Uint128,
     pub paid_by: Addr,
     pub split_between: Vec<Addr>, // Who shares this expense
+    pub split_mode: SplitMode,
+    pub shares: Vec<Share>,       // Resolved amount per member in split_between
+    #[serde(default)]
+    pub payers: Vec<Contribution>,     // Who paid how much, including paid_by
     pub timestamp: Timestamp,
-    pub settled: bool,
+    pub settled: bool,            // Every share is settled
+    #[serde(default)]
+    pub category: Option<String>, // One of the group's categories
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub occurred_at: Timestamp,   // When the expense happened, as opposed to when it was recorded
+    #[serde(default)]
+    pub status: ExpenseStatus,
+    #[serde(default)]
+    pub recorded_by: Option<Addr>, // Set when someone other than the payer recorded the expense
+    #[serde(default)]
+    pub confirmations: Vec<Addr>,  // Payers who vouched for what a pending expense credits them with
+    #[serde(default)]
+    pub approvals: Vec<Addr>,      // Members who approved this version of the expense
+    #[serde(default)]
+    pub disputes: Vec<Dispute>,    // Every dispute raised against the expense, oldest first
+}
+// A member's objection to an expense. While it's open on an active expense, the
+// member's share is left out of the group's debts.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Dispute {
+    pub member: Addr,
+    pub reason: String,
+    pub raised_at: Timestamp,
+    pub outcome: Option<DisputeOutcome>, // None while the dispute is open
+    pub resolved_by: Option<Addr>,
+    pub resolved_at: Option<Timestamp>,
+}
+// How a dispute was closed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeOutcome {
+    Upheld,    // The expense stands as it was
+    Withdrawn, // The member who raised it dropped it
+    Amended,   // The expense was changed
+    Cancelled, // The expense was removed
+}
+// Whether an expense counts towards the group's debts yet
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpenseStatus {
+    #[default]
+    Active,              // Its shares are part of the group's debts
+    PendingConfirmation, // Recorded on the payer's behalf; waiting for them to confirm it
+    PendingApproval,     // Waiting for the members it charges to approve it
+    Disputed,            // A member objected before it was approved; it needs editing or deleting
+}
+// What happened to an expense when a revision was recorded
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpenseChange {
+    Edited,
+    Deleted,
+}
+// A previous version of an expense, kept for auditing
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ExpenseRevision {
+    pub expense: Expense,       // The expense as it was before the change
+    pub change: ExpenseChange,
+    pub changed_by: Addr,
+    pub changed_at: Timestamp,
+}
+// What an expense charges one member towards one of its payers, and how much of that
+// has been paid off
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Charge {
+    pub amount: Uint128,
+    pub paid: Uint128,
 }
 // Tracks a debt between two users
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
@@ -30,6 +238,9 @@ pub struct Debt {
     pub creditor: Addr,  // User who is owed money
     pub amount: Uint128, // Amount owed
 }
+// Store the contract config
+pub const CONFIG: Item<Config> = Item::new("config");
+
 // Store counters for auto-incrementing IDs
 pub const GROUP_COUNT: Item<u64> = Item::new("group_count");
 pub const EXPENSE_COUNT: Item<u64> = Item::new("expense_count");
@@ -46,5 +257,50 @@ pub const USER_GROUPS: Map<&Addr, Vec<u64>> = Map::new("user_groups");
 // Map a group to the expenses associated with it: group_id -> Vec<expense_id>
 pub const GROUP_EXPENSES: Map<u64, Vec<u64>> = Map::new("group_expenses");
 
+// A group's expenses in date order, so they can be paged through by date:
+// (group_id, recorded or occurred time in nanoseconds, expense_id)
+pub const EXPENSES_BY_RECORDED: Map<(u64, u64, u64), Empty> = Map::new("expenses_by_recorded");
+pub const EXPENSES_BY_OCCURRED: Map<(u64, u64, u64), Empty> = Map::new("expenses_by_occurred");
+
+// Expenses waiting on someone, i.e. pending or with an open dispute: (group_id, expense_id)
+pub const OPEN_EXPENSES: Map<(u64, u64), Empty> = Map::new("open_expenses");
+
+// Charges of active expenses, per debt edge in expense order:
+// ((group_id, debtor), creditor, expense_id). Those not yet paid off in full
+// are kept apart, so paying a debt only walks what's still outstanding.
+pub const UNPAID_CHARGES: Map<((u64, &Addr), &Addr, u64), Charge> = Map::new("unpaid_charges");
+pub const PAID_CHARGES: Map<((u64, &Addr), &Addr, u64), Uint128> = Map::new("paid_charges");
+// Whose debt edges hold the charges of each share, as simplification can move them
+// off the edges to the payers: (expense_id, member) -> creditors
+pub const CHARGED_TO: Map<(u64, &Addr), Vec<Addr>> = Map::new("charged_to");
+
+// Previous versions of edited or deleted expenses: expense_id -> Vec<ExpenseRevision> (oldest first)
+pub const EXPENSE_HISTORY: Map<u64, Vec<ExpenseRevision>> = Map::new("expense_history");
+
 // Store debts by (group_id, debtor, creditor) -> amount
 pub const DEBTS: Map<(u64, &Addr, &Addr), Uint128> = Map::new("debts");
+
+// Roles other than the defaults: (group_id, member) -> role.
+// The group creator is always the owner and anyone else without an entry is a regular member.
+pub const GROUP_ROLES: Map<(u64, &Addr), GroupRole> = Map::new("group_roles");
+
+// Proposed ownership transfers: group_id -> pending transfer
+pub const PENDING_OWNERS: Map<u64, PendingOwnership> = Map::new("pending_owners");
+
+// Pending invites: (group_id, invitee) -> invite
+pub const INVITES: Map<(u64, &Addr), Invite> = Map::new("invites");
+
+// Map a user to the groups they have been invited to: user_addr -> Vec<group_id>
+pub const USER_INVITES: Map<&Addr, Vec<u64>> = Map::new("user_invites");
+
+// Join codes by their public key: public_key -> join code
+pub const JOIN_CODES: Map<&str, JoinCode> = Map::new("join_codes");
+
+// Map a group to the public keys of its join codes: group_id -> Vec<public_key>
+pub const GROUP_JOIN_CODES: Map<u64, Vec<String>> = Map::new("group_join_codes");
+
+// Pending join requests: (group_id, requester) -> request
+pub const JOIN_REQUESTS: Map<(u64, &Addr), JoinRequest> = Map::new("join_requests");
+
+// Members who agreed to have the group's debts replaced by the simplified set: group_id -> Vec<member>
+pub const SIMPLIFY_OPT_INS: Map<u64, Vec<Addr>> = Map::new("simplify_opt_ins");
@@ -181,20 +181,16 @@ export default function GroupBalancesPage() {
               const [user1, user2] = key.split('-');
               const otherUser = user1 === account.bech32Address ? user2 : user1;
               
-              // Determine if the current user owes money or is owed money
-              const direction = amount < BigInt(0) ? 0 : 1; // 0 = you owe, 1 = they owe you
-              const absAmount = amount < BigInt(0) ? -amount : amount;
-              
-              if (direction === 0) {
-                totalOwed = totalOwed + absAmount;
+              // Negative amounts are owed by the current user, positive ones are owed to them
+              if (amount < BigInt(0)) {
+                totalOwed = totalOwed - amount;
               } else {
-                totalOwedTo = totalOwedTo + absAmount;
+                totalOwedTo = totalOwedTo + amount;
               }
               
               balanceSummaries.push({
                 other_user: otherUser,
-                amount: absAmount.toString(),
-                direction
+                amount: amount.toString()
               });
             }
             
@@ -248,7 +244,7 @@ export default function GroupBalancesPage() {
       
       // Verify this debt is relevant to current user by checking balances
       const relevantBalance = balanceSummary?.balances?.find(
-        b => b.other_user === toAddress && b.direction === 0 && b.amount === amount
+        b => b.other_user === toAddress && b.amount === `-${amount}`
       );
       
       if (!relevantBalance) {
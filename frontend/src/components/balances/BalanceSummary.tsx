@@ -13,6 +13,10 @@ type BalanceSummaryProps = {
   isSettlingAll?: boolean;
 };
 
+// Balances are signed: negative amounts are owed by the current user
+const youOwe = (amount: string) => BigInt(amount) < BigInt(0);
+const absoluteAmount = (amount: string) => amount.replace(/^-/, '');
+
 export default function BalanceSummary({
   summary,
   currentUser,
@@ -23,7 +27,7 @@ export default function BalanceSummary({
 }: BalanceSummaryProps) {
   // Sort balances: first show what user owes, then what others owe to user
   const sortedBalances = summary.balances && summary.balances.length > 0 
-    ? [...summary.balances].sort((a, b) => a.direction - b.direction)
+    ? [...summary.balances].sort((a, b) => Number(youOwe(b.amount)) - Number(youOwe(a.amount)))
     : [];
   
   // Check if we have any balances to display
@@ -82,7 +86,7 @@ export default function BalanceSummary({
           }`}>
             {netBalanceNum > 0 && <ArrowDownIcon className="h-4 w-4 mr-1 flex-shrink-0" />}
             {netBalanceNum < 0 && <ArrowUpIcon className="h-4 w-4 mr-1 flex-shrink-0" />}
-            {formatCurrency(netBalance)}
+            {formatCurrency(absoluteAmount(netBalance))}
           </p>
         </div>
       </div>
@@ -123,7 +127,7 @@ export default function BalanceSummary({
         ) : (
           <div className="space-y-4">
             {/* What you owe to others */}
-            {sortedBalances.filter(b => b.direction === 0).map((balance, index) => (
+            {sortedBalances.filter(b => youOwe(b.amount)).map((balance, index) => (
               <div key={index} className="flex items-center justify-between p-4 bg-rose-50/30 dark:bg-rose-950/20 rounded-xl transition-all border border-rose-200/30 dark:border-rose-800/30 animate-slide-in" style={{ animationDelay: `${index * 50}ms` }}>
                 <div className="flex items-center gap-3">
                   <div className="h-12 w-12 rounded-full bg-gradient-to-br from-rose-100 to-rose-200 dark:from-rose-900/30 dark:to-rose-800/50 border border-rose-200 dark:border-rose-800 text-rose-500 flex items-center justify-center shadow-sm">
@@ -131,7 +135,7 @@ export default function BalanceSummary({
                   </div>
                   <div>
                     <p className="text-sm font-medium text-rose-700 dark:text-rose-300">You owe {formatAddress(balance.other_user)}</p>
-                    <p className="text-lg font-bold text-destructive">{formatCurrency(balance.amount)}</p>
+                    <p className="text-lg font-bold text-destructive">{formatCurrency(absoluteAmount(balance.amount))}</p>
                   </div>
                 </div>
                 <button 
@@ -144,8 +148,8 @@ export default function BalanceSummary({
             ))}
             
             {/* What others owe to you */}
-            {sortedBalances.filter(b => b.direction === 1).map((balance, index) => (
-              <div key={index} className="flex items-center justify-between p-4 bg-emerald-50/30 dark:bg-emerald-950/20 rounded-xl transition-all border border-emerald-200/30 dark:border-emerald-800/30 animate-slide-in" style={{ animationDelay: `${(index + sortedBalances.filter(b => b.direction === 0).length) * 50}ms` }}>
+            {sortedBalances.filter(b => !youOwe(b.amount)).map((balance, index) => (
+              <div key={index} className="flex items-center justify-between p-4 bg-emerald-50/30 dark:bg-emerald-950/20 rounded-xl transition-all border border-emerald-200/30 dark:border-emerald-800/30 animate-slide-in" style={{ animationDelay: `${(index + sortedBalances.filter(b => youOwe(b.amount)).length) * 50}ms` }}>
                 <div className="flex items-center gap-3">
                   <div className="h-12 w-12 rounded-full bg-gradient-to-br from-emerald-100 to-emerald-200 dark:from-emerald-900/30 dark:to-emerald-800/50 border border-emerald-200 dark:border-emerald-800 text-emerald-500 flex items-center justify-center shadow-sm">
                     <ArrowDownIcon className="h-5 w-5" />
@@ -29,11 +29,10 @@ export type Debt = {
 export type BalanceSummary = {
   total_owed: string;
   total_owed_to: string;
-  net_balance: string;
+  net_balance: string; // Signed: negative if you owe more than you are owed
   balances: {
     other_user: string;
-    amount: string;
-    direction: number; // 0 = you owe them, 1 = they owe you
+    amount: string; // Signed: negative if you owe them, positive if they owe you
   }[];
 };
 